@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS `patreon_pledge` (
+  `patreon_id` VARCHAR(32) NOT NULL,
+  `ckey` VARCHAR(32) NULL DEFAULT NULL,
+  `full_name` VARCHAR(255) NULL DEFAULT NULL,
+  `patron_status` VARCHAR(32) NULL DEFAULT NULL,
+  `entitled_cents` INT UNSIGNED NOT NULL DEFAULT 0,
+  `tier_ids` VARCHAR(255) NOT NULL DEFAULT '',
+  `last_event` VARCHAR(64) NOT NULL,
+  `updated_at` DATETIME NOT NULL,
+  PRIMARY KEY (`patreon_id`),
+  UNIQUE KEY `idx_ckey` (`ckey`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use rocket::{
+    form::{self, FromFormField, ValueField},
+    request::FromParam,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const MAX_LENGTH: usize = 32;
+const ALLOWED_PUNCTUATION: [char; 5] = [' ', '_', '-', '.', '@'];
+
+// https://www.byond.com/docs/ref/#/proc/ckey
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Ckey(#[serde(deserialize_with = "deserialize")] String);
+
+impl Ckey {
+    pub fn new(key: &str) -> Result<Self, Error> {
+        if let Some(c) = key
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !ALLOWED_PUNCTUATION.contains(c))
+        {
+            return Err(Error::InvalidCharacter(c));
+        }
+
+        let ckey = key
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '@')
+            .map(|c| c.to_ascii_lowercase())
+            .collect::<String>();
+
+        if ckey.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        if ckey.len() > MAX_LENGTH {
+            return Err(Error::TooLong);
+        }
+
+        Ok(Self(ckey))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Ckey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn deserialize<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let key = String::deserialize(deserializer)?;
+    Ckey::new(&key)
+        .map(|ckey| ckey.0)
+        .map_err(serde::de::Error::custom)
+}
+
+impl<'a> FromParam<'a> for Ckey {
+    type Error = Error;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Ckey::new(param)
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Ckey {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        Ok(Ckey::new(field.value).map_err(|e| form::Error::validation(e.to_string()))?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("ckey is empty")]
+    Empty,
+    #[error("ckey is longer than {MAX_LENGTH} characters")]
+    TooLong,
+    #[error("ckey contains invalid character {0:?}")]
+    InvalidCharacter(char),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonicalizes_case_and_punctuation() {
+        assert_eq!(Ckey::new("Some Key").unwrap().as_str(), "somekey");
+        assert_eq!(Ckey::new("Mr_Some-Key.").unwrap().as_str(), "mrsomekey");
+        assert_eq!(Ckey::new("Guest@Byond").unwrap().as_str(), "guest@byond");
+    }
+
+    #[test]
+    fn rejects_invalid_characters() {
+        assert!(matches!(
+            Ckey::new("some/key"),
+            Err(Error::InvalidCharacter('/'))
+        ));
+        assert!(matches!(
+            Ckey::new("sömekey"),
+            Err(Error::InvalidCharacter('ö'))
+        ));
+    }
+
+    #[test]
+    fn rejects_empty() {
+        assert!(matches!(Ckey::new(""), Err(Error::Empty)));
+        assert!(matches!(Ckey::new(" _-. "), Err(Error::Empty)));
+    }
+
+    #[test]
+    fn rejects_over_length() {
+        let key = "a".repeat(MAX_LENGTH);
+        assert_eq!(Ckey::new(&key).unwrap().as_str(), key);
+
+        assert!(matches!(
+            Ckey::new(&"a".repeat(MAX_LENGTH + 1)),
+            Err(Error::TooLong)
+        ));
+
+        // punctuation is stripped before the length check
+        let spaced = format!("{key}    ");
+        assert!(Ckey::new(&spaced).is_ok());
+    }
+}
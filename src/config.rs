@@ -1,6 +1,14 @@
-use rocket::config::LogLevel;
-use serde::Deserialize;
-use std::{collections::HashSet, fs::read_to_string, net::IpAddr};
+use chrono_tz::Tz;
+use rocket::config::{LogLevel, SecretKey};
+use serde::{
+    de::{Error as _, IntoDeserializer as _},
+    Deserialize, Deserializer,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_to_string,
+    net::IpAddr,
+};
 use thiserror::Error;
 
 #[derive(Debug, Deserialize)]
@@ -12,18 +20,46 @@ pub struct Config {
     pub dev_routes: HashSet<String>,
     pub exposed_secret: String,
     pub exposed_routes: HashSet<String>,
+    #[serde(deserialize_with = "deserialize_secret_key")]
+    pub secret_key: SecretKey,
     pub discord: Discord,
+    pub patreon: Patreon,
     pub cli_colors: bool,
     pub log_level: LogLevel,
     pub database: Database,
     pub servers: Vec<Server>,
+    #[serde(default)]
+    pub departments: HashMap<String, Vec<String>>,
+    // special roles whose deaths don't count as a player's own characters
+    #[serde(default)]
+    pub excluded_roles: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Discord {
     pub token: String,
     pub guild: i64,
-    pub patreon_role: i64,
+    pub patreon_tiers: Vec<PatreonTier>,
+    pub patrons_refresh_interval: u64,
+    pub client_id: i64,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub login_redirect: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatreonTier {
+    pub role: i64,
+    pub name: String,
+    pub rank: u32,
+    pub tier_id: Option<String>,
+    #[serde(default)]
+    pub perks: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Patreon {
+    pub webhook_secret: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +69,9 @@ pub struct Database {
     pub host: IpAddr,
     pub port: u16,
     pub database: String,
+    // the game writes NOW() in the server's local time, so timestamps are read in this zone
+    #[serde(default, deserialize_with = "deserialize_timezone")]
+    pub timezone: Tz,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,6 +86,54 @@ impl Config {
     pub fn read_from_file() -> Result<Self, Error> {
         Ok(toml::from_str(&read_to_string("config.toml")?)?)
     }
+
+    // the database only knows servers by port, so names are matched through the configured address
+    pub fn server_port(&self, name: &str) -> Option<u16> {
+        self.servers
+            .iter()
+            .find(|server| server.name == name)
+            .and_then(|server| server.port())
+    }
+
+    pub fn server_name(&self, port: u16) -> Option<&str> {
+        self.servers
+            .iter()
+            .find(|server| server.port() == Some(port))
+            .map(|server| server.name.as_str())
+    }
+}
+
+// sessions are private cookies, so refuse to start with a missing or all zero key
+fn deserialize_secret_key<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<SecretKey, D::Error> {
+    let key = String::deserialize(deserializer)?;
+
+    if key.is_empty() {
+        return Err(D::Error::custom(
+            "secret_key must be set, generate one with `openssl rand -base64 32`",
+        ));
+    }
+
+    let key = SecretKey::deserialize(key.into_deserializer())?;
+
+    if key.is_zero() {
+        return Err(D::Error::custom("secret_key must not be all zeroes"));
+    }
+
+    Ok(key)
+}
+
+fn deserialize_timezone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tz, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(D::Error::custom)
+}
+
+impl Server {
+    pub fn port(&self) -> Option<u16> {
+        self.address.rsplit(':').next()?.parse().ok()
+    }
 }
 
 #[derive(Debug, Error)]
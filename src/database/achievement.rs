@@ -0,0 +1,188 @@
+use chrono::NaiveDateTime;
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
+use sqlx::{pool::PoolConnection, Executor as _, FromRow, MySql, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::{error::Error, player_exists};
+
+const UNLOCKS: &str = "(SELECT COUNT(*) FROM achievements unlocked WHERE unlocked.achievement_key = achievement_metadata.achievement_key AND unlocked.value > 0)";
+
+// how many of all known players have unlocked it, as a percentage
+fn rarity(unlocks: i64, players: i64) -> f64 {
+    match players {
+        0 => 0.0,
+        players => (unlocks as f64 / players as f64 * 10000.0).round() / 100.0,
+    }
+}
+
+async fn count_players(connection: &mut PoolConnection<MySql>) -> Result<i64, Error> {
+    let players = sqlx::query_scalar("SELECT COUNT(*) FROM player")
+        .fetch_one(&mut **connection)
+        .await?;
+
+    Ok(players)
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Achievement {
+    pub achievement_key: String,
+    pub achievement_version: u16,
+    pub achievement_type: Option<String>,
+    pub achievement_name: Option<String>,
+    pub achievement_description: Option<String>,
+    pub value: Option<i32>,
+    pub unlocks: i64,
+    #[sqlx(skip)]
+    pub rarity: f64,
+}
+
+pub async fn get_achievements(ckey: &Ckey, pool: &MySqlPool) -> Result<Vec<Achievement>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    if !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    let sql = format!(
+        "SELECT achievement_metadata.*, achievements.value, {UNLOCKS} AS unlocks FROM achievements JOIN achievement_metadata ON achievements.achievement_key = achievement_metadata.achievement_key WHERE achievements.ckey = ?"
+    );
+
+    let query = sqlx::query_as(&sql).bind(ckey.as_str());
+
+    let mut achievements: Vec<Achievement> = query.fetch_all(&mut *connection).await?;
+
+    let players = count_players(&mut connection).await?;
+
+    connection.close().await?;
+
+    for achievement in &mut achievements {
+        achievement.rarity = rarity(achievement.unlocks, players);
+    }
+
+    Ok(achievements)
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct CatalogAchievement {
+    pub achievement_key: String,
+    pub achievement_version: u16,
+    pub achievement_type: Option<String>,
+    pub achievement_name: Option<String>,
+    pub achievement_description: Option<String>,
+    pub unlocks: i64,
+    #[sqlx(skip)]
+    pub rarity: f64,
+}
+
+pub async fn get_achievement_catalog(pool: &MySqlPool) -> Result<Vec<CatalogAchievement>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let sql = format!(
+        "SELECT achievement_metadata.*, {UNLOCKS} AS unlocks FROM achievement_metadata ORDER BY unlocks ASC, achievement_key ASC"
+    );
+
+    let mut catalog: Vec<CatalogAchievement> =
+        sqlx::query_as(&sql).fetch_all(&mut *connection).await?;
+
+    let players = count_players(&mut connection).await?;
+
+    connection.close().await?;
+
+    for achievement in &mut catalog {
+        achievement.rarity = rarity(achievement.unlocks, players);
+    }
+
+    Ok(catalog)
+}
+
+#[derive(Debug, Serialize)]
+pub struct AchievementHolder {
+    pub ckey: String,
+    pub value: Option<i32>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub last_updated: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AchievementDetails {
+    #[serde(flatten)]
+    pub achievement: CatalogAchievement,
+    pub recent: Vec<AchievementHolder>,
+    pub top_scores: Option<Vec<AchievementHolder>>,
+}
+
+async fn get_holders(
+    key: &str,
+    order_by: &str,
+    limit: u32,
+    connection: &mut PoolConnection<MySql>,
+) -> Result<Vec<AchievementHolder>, Error> {
+    let sql = format!(
+        "SELECT ckey, value, last_updated FROM achievements WHERE achievement_key = ? AND value > 0 ORDER BY {order_by} LIMIT ?"
+    );
+
+    let query = sqlx::query(&sql).bind(key).bind(limit);
+
+    let mut holders = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            holders.push(AchievementHolder {
+                ckey: row.try_get("ckey")?,
+                value: row.try_get("value")?,
+                last_updated: row.try_get("last_updated")?,
+            });
+        }
+    }
+
+    Ok(holders)
+}
+
+pub async fn get_achievement_details(
+    key: &str,
+    limit: u32,
+    pool: &MySqlPool,
+) -> Result<Option<AchievementDetails>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let sql = format!(
+        "SELECT achievement_metadata.*, {UNLOCKS} AS unlocks FROM achievement_metadata WHERE achievement_key = ?"
+    );
+
+    let achievement: Option<CatalogAchievement> = sqlx::query_as(&sql)
+        .bind(key)
+        .fetch_optional(&mut *connection)
+        .await?;
+
+    let Some(mut achievement) = achievement else {
+        connection.close().await?;
+        return Ok(None);
+    };
+
+    achievement.rarity = rarity(achievement.unlocks, count_players(&mut connection).await?);
+
+    let recent = get_holders(key, "last_updated DESC", limit, &mut connection).await?;
+
+    // only score achievements have a value worth ranking, the rest are just unlocked or not
+    let top_scores = match achievement.achievement_type.as_deref() {
+        Some("score") => {
+            Some(get_holders(key, "value DESC, last_updated ASC", limit, &mut connection).await?)
+        }
+        _ => None,
+    };
+
+    connection.close().await?;
+
+    Ok(Some(AchievementDetails {
+        achievement,
+        recent,
+        top_scores,
+    }))
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::{futures::StreamExt as _, FromFormField};
+use serde::Serialize;
+use sqlx::{Executor as _, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::error::Error;
+
+// R_* defines from code/__DEFINES/admin.dm, in bit order
+const PERMISSIONS: [&str; 15] = [
+    "build",
+    "admin",
+    "ban",
+    "fun",
+    "server",
+    "debug",
+    "possess",
+    "permissions",
+    "stealth",
+    "poll",
+    "varedit",
+    "sound",
+    "spawn",
+    "autoadmin",
+    "dbranks",
+];
+
+pub fn decode_flags(flags: u16) -> Vec<&'static str> {
+    PERMISSIONS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| flags & (1 << bit) != 0)
+        .map(|(_, permission)| *permission)
+        .collect()
+}
+
+// composite ranks are joined with a +, like /datum/admin_rank/New each rank's exclusions only
+// mask its own flags, and the composite grants the union of what is left
+fn combine_rank_flags(ranks: &[String], rank_flags: &HashMap<String, (u16, u16)>) -> u16 {
+    ranks
+        .iter()
+        .filter_map(|rank| rank_flags.get(rank))
+        .fold(0, |combined, (flags, exclude_flags)| {
+            combined | (flags & !exclude_flags)
+        })
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminActivity {
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub last_seen: Option<NaiveDateTime>,
+    pub playtime_minutes: i64,
+    pub recent_minutes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Admin {
+    pub ckey: String,
+    pub rank: String,
+    pub ranks: Vec<String>,
+    pub flags: u16,
+    pub permissions: Vec<&'static str>,
+    pub discord_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<AdminActivity>,
+}
+
+// days of role_time_log counted towards recent playtime
+const RECENT_DAYS: u32 = 30;
+
+pub async fn get_admins(include_activity: bool, pool: &MySqlPool) -> Result<Vec<Admin>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut rank_flags = HashMap::new();
+
+    {
+        let mut rows = connection.fetch("SELECT `rank`, flags, exclude_flags FROM admin_ranks");
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let rank: String = row.try_get("rank")?;
+            let flags: u16 = row.try_get("flags")?;
+            let exclude_flags: u16 = row.try_get("exclude_flags")?;
+
+            rank_flags.insert(rank, (flags, exclude_flags));
+        }
+    }
+
+    let mut sql =
+        "SELECT admin.ckey, admin.`rank`, CAST(discord_links.discord_id AS CHAR) AS discord_id"
+            .to_string();
+
+    if include_activity {
+        sql.push_str(&format!(
+            ", player.lastseen, (SELECT CAST(COALESCE(SUM(minutes), 0) AS SIGNED) FROM role_time WHERE role_time.ckey = admin.ckey AND job IN ('Living', 'Ghost')) AS playtime_minutes, (SELECT CAST(COALESCE(SUM(delta), 0) AS SIGNED) FROM role_time_log WHERE role_time_log.ckey = admin.ckey AND job IN ('Living', 'Ghost') AND datetime >= NOW() - INTERVAL {RECENT_DAYS} DAY) AS recent_minutes"
+        ));
+    }
+
+    sql.push_str(" FROM admin LEFT JOIN discord_links ON discord_links.ckey = admin.ckey AND discord_links.valid = 1");
+
+    if include_activity {
+        sql.push_str(" LEFT JOIN player ON player.ckey = admin.ckey");
+    }
+
+    sql.push_str(" ORDER BY admin.ckey ASC");
+
+    let mut admins = Vec::new();
+
+    {
+        let mut rows = connection.fetch(sql.as_str());
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let rank: String = row.try_get("rank")?;
+
+            let ranks = rank.split('+').map(str::to_string).collect::<Vec<_>>();
+            let flags = combine_rank_flags(&ranks, &rank_flags);
+
+            let activity = match include_activity {
+                true => Some(AdminActivity {
+                    last_seen: row.try_get("lastseen")?,
+                    playtime_minutes: row.try_get("playtime_minutes")?,
+                    recent_minutes: row.try_get("recent_minutes")?,
+                }),
+                false => None,
+            };
+
+            admins.push(Admin {
+                ckey: row.try_get("ckey")?,
+                rank,
+                ranks,
+                flags,
+                permissions: decode_flags(flags),
+                discord_id: row.try_get("discord_id")?,
+                activity,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(admins)
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminStats {
+    pub ckey: String,
+    pub bans: i64,
+    pub notes: i64,
+    pub rank_changes: i64,
+}
+
+pub async fn get_admin_activity(
+    from: NaiveDate,
+    to: NaiveDate,
+    pool: &MySqlPool,
+) -> Result<Vec<AdminStats>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    // bans on several roles share a bantime, so those are counted once. admin additions and
+    // removals are in admin_log too but aren't rank changes
+    let query = sqlx::query(
+        "SELECT ckey, CAST(SUM(bans) AS SIGNED) AS bans, CAST(SUM(notes) AS SIGNED) AS notes, CAST(SUM(rank_changes) AS SIGNED) AS rank_changes FROM (SELECT a_ckey AS ckey, COUNT(DISTINCT bantime, ckey) AS bans, 0 AS notes, 0 AS rank_changes FROM ban WHERE bantime >= ? AND bantime < DATE_ADD(?, INTERVAL 1 DAY) GROUP BY a_ckey UNION ALL SELECT adminckey, 0, COUNT(*), 0 FROM messages WHERE type = 'note' AND timestamp >= ? AND timestamp < DATE_ADD(?, INTERVAL 1 DAY) GROUP BY adminckey UNION ALL SELECT adminckey, 0, 0, COUNT(*) FROM admin_log WHERE operation IN ('change admin rank', 'add rank', 'remove rank', 'change rank flags') AND datetime >= ? AND datetime < DATE_ADD(?, INTERVAL 1 DAY) GROUP BY adminckey) AS activity GROUP BY ckey ORDER BY ckey ASC"
+    )
+    .bind(from)
+    .bind(to)
+    .bind(from)
+    .bind(to)
+    .bind(from)
+    .bind(to);
+
+    let mut stats = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            stats.push(AdminStats {
+                ckey: row.try_get("ckey")?,
+                bans: row.try_get("bans")?,
+                notes: row.try_get("notes")?,
+                rank_changes: row.try_get("rank_changes")?,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(stats)
+}
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum AdminLogOperation {
+    #[field(value = "add admin")]
+    #[field(value = "add_admin")]
+    AddAdmin,
+    #[field(value = "remove admin")]
+    #[field(value = "remove_admin")]
+    RemoveAdmin,
+    #[field(value = "change admin rank")]
+    #[field(value = "change_admin_rank")]
+    ChangeAdminRank,
+    #[field(value = "add rank")]
+    #[field(value = "add_rank")]
+    AddRank,
+    #[field(value = "remove rank")]
+    #[field(value = "remove_rank")]
+    RemoveRank,
+    #[field(value = "change rank flags")]
+    #[field(value = "change_rank_flags")]
+    ChangeRankFlags,
+}
+
+impl AdminLogOperation {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            AdminLogOperation::AddAdmin => "add admin",
+            AdminLogOperation::RemoveAdmin => "remove admin",
+            AdminLogOperation::ChangeAdminRank => "change admin rank",
+            AdminLogOperation::AddRank => "add rank",
+            AdminLogOperation::RemoveRank => "remove rank",
+            AdminLogOperation::ChangeRankFlags => "change rank flags",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AdminLogFilter<'a> {
+    pub admin: Option<&'a Ckey>,
+    pub target: Option<&'a str>,
+    pub operation: Option<AdminLogOperation>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminLogEntry {
+    pub id: u32,
+    #[serde(with = "crate::serde::datetime")]
+    pub datetime: NaiveDateTime,
+    pub round_id: Option<u32>,
+    pub adminckey: String,
+    pub operation: String,
+    pub target: String,
+    pub log: String,
+}
+
+pub async fn get_admin_log(
+    filter: &AdminLogFilter<'_>,
+    after: Option<u32>,
+    limit: u32,
+    pool: &MySqlPool,
+) -> Result<Vec<AdminLogEntry>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql =
+        "SELECT id, datetime, round_id, adminckey, operation, target, log FROM admin_log WHERE 1 = 1"
+            .to_string();
+
+    if filter.admin.is_some() {
+        sql.push_str(" AND adminckey = ?");
+    }
+
+    if filter.target.is_some() {
+        sql.push_str(" AND target = ?");
+    }
+
+    if filter.operation.is_some() {
+        sql.push_str(" AND operation = ?");
+    }
+
+    if filter.from.is_some() {
+        sql.push_str(" AND datetime >= ?");
+    }
+
+    if filter.to.is_some() {
+        sql.push_str(" AND datetime < DATE_ADD(?, INTERVAL 1 DAY)");
+    }
+
+    if after.is_some() {
+        sql.push_str(" AND id < ?");
+    }
+
+    sql.push_str(" ORDER BY id DESC LIMIT ?");
+
+    let mut query = sqlx::query(&sql);
+
+    if let Some(admin) = filter.admin {
+        query = query.bind(admin.as_str());
+    }
+
+    if let Some(target) = filter.target {
+        query = query.bind(target);
+    }
+
+    if let Some(operation) = filter.operation {
+        query = query.bind(operation.as_sql());
+    }
+
+    if let Some(from) = filter.from {
+        query = query.bind(from);
+    }
+
+    if let Some(to) = filter.to {
+        query = query.bind(to);
+    }
+
+    if let Some(after) = after {
+        query = query.bind(after);
+    }
+
+    query = query.bind(limit);
+
+    let mut entries = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            entries.push(AdminLogEntry {
+                id: row.try_get("id")?,
+                datetime: row.try_get("datetime")?,
+                round_id: row.try_get("round_id")?,
+                adminckey: row.try_get("adminckey")?,
+                operation: row.try_get("operation")?,
+                target: row.try_get("target")?,
+                log: row.try_get("log")?,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // R_BUILD, R_ADMIN, R_BAN, R_FUN
+    const BUILD: u16 = 1 << 0;
+    const ADMIN: u16 = 1 << 1;
+    const BAN: u16 = 1 << 2;
+    const FUN: u16 = 1 << 3;
+
+    fn ranks(ranks: &[&str]) -> Vec<String> {
+        ranks.iter().map(|rank| rank.to_string()).collect()
+    }
+
+    #[test]
+    fn decodes_flags_in_bit_order() {
+        assert_eq!(decode_flags(0), Vec::<&str>::new());
+        assert_eq!(decode_flags(ADMIN | BAN), ["admin", "ban"]);
+        assert_eq!(decode_flags(1 << 14), ["dbranks"]);
+        assert_eq!(decode_flags(u16::MAX).len(), PERMISSIONS.len());
+    }
+
+    #[test]
+    fn exclusions_only_mask_their_own_rank() {
+        let rank_flags = HashMap::from([
+            ("Admin".to_string(), (ADMIN | BAN | FUN, FUN)),
+            ("Fun".to_string(), (FUN, 0)),
+        ]);
+
+        // Admin's exclusion of fun doesn't take away what Fun grants
+        assert_eq!(
+            combine_rank_flags(&ranks(&["Admin", "Fun"]), &rank_flags),
+            ADMIN | BAN | FUN
+        );
+        assert_eq!(
+            combine_rank_flags(&ranks(&["Admin"]), &rank_flags),
+            ADMIN | BAN
+        );
+    }
+
+    #[test]
+    fn unknown_ranks_grant_nothing() {
+        let rank_flags = HashMap::from([("Builder".to_string(), (BUILD, 0))]);
+
+        assert_eq!(
+            combine_rank_flags(&ranks(&["Builder", "Missing"]), &rank_flags),
+            BUILD
+        );
+        assert_eq!(combine_rank_flags(&ranks(&["Missing"]), &rank_flags), 0);
+    }
+}
@@ -0,0 +1,295 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::{futures::StreamExt as _, FromFormField};
+use serde::Serialize;
+use sqlx::{mysql::MySqlRow, Executor as _, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::{error::Error, escape_like, player_exists};
+
+// multi-role bans are inserted as one row per role and there's no batch id, so a set is keyed by
+// everything the ban was placed with. two bans placed in the same second only merge when they
+// are the same ban. per-role unbans are folded: active while any role is, unbanned once all are
+const BAN_SET_SELECT: &str = "SELECT CAST(GROUP_CONCAT(id ORDER BY id) AS CHAR) AS ids, bantime, server_port, round_id, GROUP_CONCAT(role ORDER BY role SEPARATOR ', ') AS roles, expiration_time, reason, ckey, a_ckey, MAX(edits) AS edits, IF(COUNT(unbanned_datetime) < COUNT(*), NULL, MAX(unbanned_datetime)) AS unbanned_datetime, IF(COUNT(unbanned_datetime) < COUNT(*), NULL, MAX(unbanned_ckey)) AS unbanned_ckey, CAST(MAX(unbanned_datetime IS NULL AND (expiration_time IS NULL OR expiration_time > NOW())) AS SIGNED) AS active, IF(MAX(unbanned_datetime IS NULL AND expiration_time IS NULL), NULL, MAX(IF(unbanned_datetime IS NULL AND expiration_time > NOW(), TIMESTAMPDIFF(MINUTE, NOW(), expiration_time), NULL))) AS remaining_minutes FROM ban";
+
+const BAN_SET_GROUP_BY: &str =
+    " GROUP BY bantime, ckey, a_ckey, round_id, server_port, reason, expiration_time";
+
+#[derive(Debug, Serialize)]
+pub struct Ban {
+    pub ids: Vec<u32>,
+    #[serde(with = "crate::serde::datetime")]
+    pub bantime: NaiveDateTime,
+    pub server_port: u16,
+    pub round_id: Option<u32>,
+    pub roles: Option<String>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub expiration_time: Option<NaiveDateTime>,
+    pub reason: String,
+    pub ckey: Option<String>,
+    pub a_ckey: String,
+    pub edits: Option<String>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub unbanned_datetime: Option<NaiveDateTime>,
+    pub unbanned_ckey: Option<String>,
+    pub active: bool,
+    pub remaining_minutes: Option<i64>,
+}
+
+impl Ban {
+    fn from_row(row: &MySqlRow) -> Result<Self, Error> {
+        let ids: String = row.try_get("ids")?;
+        let active = row.try_get::<i64, _>("active")? != 0;
+        let remaining_minutes: Option<i64> = row.try_get("remaining_minutes")?;
+
+        Ok(Ban {
+            ids: ids
+                .split(',')
+                .map(|id| id.parse())
+                .collect::<Result<_, _>>()?,
+            bantime: row.try_get("bantime")?,
+            server_port: row.try_get("server_port")?,
+            round_id: row.try_get("round_id")?,
+            roles: row.try_get("roles")?,
+            expiration_time: row.try_get("expiration_time")?,
+            reason: row.try_get("reason")?,
+            ckey: row.try_get("ckey")?,
+            a_ckey: row.try_get("a_ckey")?,
+            edits: row.try_get("edits")?,
+            unbanned_datetime: row.try_get("unbanned_datetime")?,
+            unbanned_ckey: row.try_get("unbanned_ckey")?,
+            active,
+            remaining_minutes: remaining_minutes.filter(|_| active),
+        })
+    }
+
+    pub fn covers_role(&self, role: &str) -> bool {
+        self.roles.as_deref().is_some_and(|roles| {
+            roles
+                .split(", ")
+                .any(|r| r.eq_ignore_ascii_case(role) || r == "Server")
+        })
+    }
+}
+
+pub async fn get_ban(
+    ckey: &Ckey,
+    permanent: bool,
+    since: Option<&str>,
+    pool: &MySqlPool,
+) -> Result<Vec<Ban>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = format!("{BAN_SET_SELECT} WHERE ckey = ?");
+
+    if permanent {
+        sql.push_str(" AND expiration_time IS NULL");
+    }
+
+    if since.is_some() {
+        sql.push_str(" AND bantime > ?");
+    }
+
+    sql.push_str(BAN_SET_GROUP_BY);
+    sql.push_str(" ORDER BY bantime DESC");
+
+    let mut query = sqlx::query(&sql).bind(ckey.as_str());
+
+    if let Some(since) = since {
+        query = query.bind(since);
+    }
+
+    let mut bans = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            bans.push(Ban::from_row(&row?)?);
+        }
+    }
+
+    if bans.is_empty() && !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    connection.close().await?;
+
+    Ok(bans)
+}
+
+pub async fn get_active_bans(
+    ckey: &Ckey,
+    role: Option<&str>,
+    pool: &MySqlPool,
+) -> Result<Vec<Ban>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let sql = format!(
+        "{BAN_SET_SELECT} WHERE ckey = ? AND unbanned_datetime IS NULL AND (expiration_time IS NULL OR expiration_time > NOW()){BAN_SET_GROUP_BY} ORDER BY bantime DESC"
+    );
+
+    let query = sqlx::query(&sql).bind(ckey.as_str());
+
+    let mut bans = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let ban = Ban::from_row(&row?)?;
+
+            if role.is_none_or(|role| ban.covers_role(role)) {
+                bans.push(ban);
+            }
+        }
+    }
+
+    if bans.is_empty() && !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    connection.close().await?;
+
+    Ok(bans)
+}
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum BanStatus {
+    Active,
+    Expired,
+    Unbanned,
+}
+
+#[derive(Debug, Default)]
+pub struct BanFilter<'a> {
+    pub ckey: Option<&'a Ckey>,
+    pub a_ckey: Option<&'a Ckey>,
+    pub role: Option<&'a str>,
+    pub status: Option<BanStatus>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub round_id: Option<u32>,
+    pub server_port: Option<u16>,
+    pub search: Option<&'a str>,
+}
+
+pub async fn search_bans(
+    filter: &BanFilter<'_>,
+    after: Option<u32>,
+    limit: u32,
+    pool: &MySqlPool,
+) -> Result<Vec<Ban>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = format!("{BAN_SET_SELECT} WHERE 1 = 1");
+
+    if filter.ckey.is_some() {
+        sql.push_str(" AND ckey = ?");
+    }
+
+    if filter.a_ckey.is_some() {
+        sql.push_str(" AND a_ckey = ?");
+    }
+
+    if filter.from.is_some() {
+        sql.push_str(" AND bantime >= ?");
+    }
+
+    if filter.to.is_some() {
+        sql.push_str(" AND bantime < DATE_ADD(?, INTERVAL 1 DAY)");
+    }
+
+    if filter.round_id.is_some() {
+        sql.push_str(" AND round_id = ?");
+    }
+
+    if filter.server_port.is_some() {
+        sql.push_str(" AND server_port = ?");
+    }
+
+    if filter.search.is_some() {
+        sql.push_str(" AND reason LIKE ?");
+    }
+
+    sql.push_str(BAN_SET_GROUP_BY);
+
+    // roles and unbans differ between the rows of a set, so they're matched on the whole set
+    sql.push_str(" HAVING 1 = 1");
+
+    if filter.role.is_some() {
+        sql.push_str(" AND MAX(role = ?)");
+    }
+
+    match filter.status {
+        Some(BanStatus::Active) => sql.push_str(" AND active = 1"),
+        Some(BanStatus::Expired) => {
+            sql.push_str(" AND active = 0 AND COUNT(unbanned_datetime) < COUNT(*)")
+        }
+        Some(BanStatus::Unbanned) => sql.push_str(" AND COUNT(unbanned_datetime) = COUNT(*)"),
+        None => {}
+    }
+
+    // sets are paged on their lowest id, which comes first in ids
+    if after.is_some() {
+        sql.push_str(" AND MIN(id) < ?");
+    }
+
+    sql.push_str(" ORDER BY MIN(id) DESC LIMIT ?");
+
+    let mut query = sqlx::query(&sql);
+
+    if let Some(ckey) = filter.ckey {
+        query = query.bind(ckey.as_str());
+    }
+
+    if let Some(a_ckey) = filter.a_ckey {
+        query = query.bind(a_ckey.as_str());
+    }
+
+    if let Some(from) = filter.from {
+        query = query.bind(from);
+    }
+
+    if let Some(to) = filter.to {
+        query = query.bind(to);
+    }
+
+    if let Some(round_id) = filter.round_id {
+        query = query.bind(round_id);
+    }
+
+    if let Some(server_port) = filter.server_port {
+        query = query.bind(server_port);
+    }
+
+    if let Some(search) = filter.search {
+        query = query.bind(format!("%{}%", escape_like(search)));
+    }
+
+    if let Some(role) = filter.role {
+        query = query.bind(role);
+    }
+
+    if let Some(after) = after {
+        query = query.bind(after);
+    }
+
+    query = query.bind(limit);
+
+    let mut bans = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            bans.push(Ban::from_row(&row?)?);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(bans)
+}
@@ -0,0 +1,71 @@
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
+use sqlx::{Executor as _, MySqlPool, Row as _};
+
+use super::{error::Error, DEATH_CKEY};
+
+#[derive(Debug, Serialize)]
+pub struct CharacterPlayer {
+    pub ckey: String,
+    pub first_seen_round: Option<u32>,
+    pub last_seen_round: Option<u32>,
+    pub jobs: Vec<String>,
+    pub deaths: i64,
+}
+
+pub async fn get_character_players(
+    name: &str,
+    excluded_roles: &[String],
+    pool: &MySqlPool,
+) -> Result<Vec<CharacterPlayer>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let excluded = match excluded_roles.is_empty() {
+        true => String::new(),
+        false => format!(
+            " AND (special IS NULL OR special NOT IN ({}))",
+            vec!["?"; excluded_roles.len()].join(", ")
+        ),
+    };
+
+    // the manifest covers every round the character spawned in, deaths only the ones it died in
+    let sql = format!(
+        "SELECT ckey, MIN(round_id) AS first_seen_round, MAX(round_id) AS last_seen_round, GROUP_CONCAT(DISTINCT job ORDER BY job SEPARATOR '\\n') AS jobs, CAST(SUM(died) AS SIGNED) AS deaths FROM (SELECT ckey, round_id, job, 0 AS died FROM manifest WHERE character_name = ?{excluded} UNION ALL SELECT {DEATH_CKEY} AS ckey, round_id, job, 1 AS died FROM death WHERE name = ?{excluded}) AS appearances GROUP BY ckey ORDER BY last_seen_round DESC"
+    );
+
+    let mut query = sqlx::query(&sql);
+
+    for _ in 0..2 {
+        query = query.bind(name);
+
+        for role in excluded_roles {
+            query = query.bind(role);
+        }
+    }
+
+    let mut players = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let jobs: Option<String> = row.try_get("jobs")?;
+
+            players.push(CharacterPlayer {
+                ckey: row.try_get("ckey")?,
+                first_seen_round: row.try_get("first_seen_round")?,
+                last_seen_round: row.try_get("last_seen_round")?,
+                jobs: jobs
+                    .map(|jobs| jobs.split('\n').map(str::to_string).collect())
+                    .unwrap_or_default(),
+                deaths: row.try_get("deaths")?,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(players)
+}
@@ -8,6 +8,7 @@ pub enum Error {
     SerdeJson(#[from] serde_json::Error),
     ParseInt(#[from] std::num::ParseIntError),
     Http(#[from] crate::http::Error),
+    Ckey(#[from] crate::ckey::Error),
     #[error("Player not found")]
     PlayerNotFound,
     #[error("Discord account is already linked to {0}")]
@@ -18,4 +19,12 @@ pub enum Error {
     NotLinked,
     #[error("Token does not exist or is invalid")]
     TokenInvalid,
+    #[error("Ckey is already linked to Patreon user {0}")]
+    PledgeInUse(String),
+    #[error("Patreon pledge not found")]
+    PledgeNotFound,
+    #[error("Patreon pledge is already linked to {0}")]
+    PledgeLinked(String),
+    #[error("Feedback can't be aggregated across these entries")]
+    FeedbackNotAggregatable,
 }
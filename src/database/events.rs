@@ -1,14 +1,19 @@
 use std::collections::HashMap;
 
-use chrono::NaiveDateTime;
-use rocket::futures::StreamExt as _;
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::{futures::StreamExt as _, FromFormField};
 use serde::Serialize;
 use serde_json::Value;
-use sqlx::{pool::PoolConnection, Executor as _, MySql, MySqlPool, Row as _};
+use sqlx::{
+    mysql::{MySqlArguments, MySqlRow},
+    pool::PoolConnection,
+    query::Query,
+    Executor as _, MySql, MySqlPool, Row as _,
+};
 
-use crate::{byond::get_server_status, config::Config};
+use crate::{byond::get_server_status, ckey::Ckey, config::Config};
 
-use super::error::Error;
+use super::{error::Error, escape_like, round_bound};
 
 #[derive(Debug, Serialize)]
 pub struct Feedback {
@@ -66,10 +71,22 @@ pub async fn get_feedback(
     Ok(feedbacks)
 }
 
+// death rows keep the raw byond key, this reduces it to a ckey the same way Ckey::new does
+pub(super) const DEATH_CKEY: &str = "LOWER(REGEXP_REPLACE(byondkey, '[^A-Za-z0-9@]', ''))";
+
+// lookups by ckey compare byondkey as stored, to the ckey and to the key the player table has
+// on record for it, rather than running DEATH_CKEY over every row; the ckey is bound twice
+pub(super) const DEATH_BY_CKEY: &str =
+    "byondkey IN (?, (SELECT byond_key FROM player WHERE ckey = ?))";
+
+pub(super) const DEATH_COLUMNS: &str = "CAST(id AS SIGNED) AS id, name, job, special, pod, bruteloss, fireloss, oxyloss, toxloss, last_words, suicide, round_id, tod";
+
 #[derive(Debug, Serialize)]
 pub struct Death {
+    pub id: i64,
     pub name: String,
     pub job: String,
+    pub special: Option<String>,
     pub pod: String,
     pub bruteloss: u16,
     pub fireloss: u16,
@@ -82,49 +99,238 @@ pub struct Death {
     pub tod: NaiveDateTime,
 }
 
-pub async fn get_deaths(
-    fetch_size: Option<i32>,
-    page: Option<i32>,
-    config: &Config,
-    pool: &MySqlPool,
-) -> Result<(Vec<Death>, i64), Error> {
-    let round_id = get_round_id(config).await?;
+impl Death {
+    pub(super) fn from_row(death: &MySqlRow) -> Result<Self, Error> {
+        Ok(Death {
+            id: death.try_get("id")?,
+            name: death.try_get("name")?,
+            job: death.try_get("job")?,
+            special: death.try_get("special")?,
+            pod: death.try_get("pod")?,
+            bruteloss: death.try_get("bruteloss")?,
+            fireloss: death.try_get("fireloss")?,
+            oxyloss: death.try_get("oxyloss")?,
+            toxloss: death.try_get("toxloss")?,
+            last_words: death.try_get("last_words")?,
+            suicide: death.try_get("suicide")?,
+            round_id: death.try_get("round_id")?,
+            tod: death.try_get("tod")?,
+        })
+    }
 
-    let fetch_size = fetch_size.unwrap_or(20);
-    let page = page.unwrap_or(1);
-    let offset = (page - 1) * fetch_size;
+    pub fn total_damage(&self) -> i64 {
+        self.bruteloss as i64 + self.fireloss as i64 + self.oxyloss as i64 + self.toxloss as i64
+    }
+}
 
-    let mut connection = pool.acquire().await?;
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum DamageType {
+    Brute,
+    Fire,
+    Oxy,
+    Tox,
+}
 
-    let mut sql = "SELECT COUNT(*) FROM death".to_string();
+impl DamageType {
+    // the given damage has to be the largest of the four, and actually present
+    fn as_sql(&self) -> &'static str {
+        match self {
+            DamageType::Brute => " AND bruteloss > 0 AND bruteloss >= fireloss AND bruteloss >= oxyloss AND bruteloss >= toxloss",
+            DamageType::Fire => " AND fireloss > 0 AND fireloss >= bruteloss AND fireloss >= oxyloss AND fireloss >= toxloss",
+            DamageType::Oxy => " AND oxyloss > 0 AND oxyloss >= bruteloss AND oxyloss >= fireloss AND oxyloss >= toxloss",
+            DamageType::Tox => " AND toxloss > 0 AND toxloss >= bruteloss AND toxloss >= fireloss AND toxloss >= oxyloss",
+        }
+    }
+}
 
-    if round_id.is_some() {
-        sql.push_str(" WHERE round_id < ?");
+// ids grow with time of death, so the chronological sorts page on the primary key alone
+#[derive(Debug, Clone, Copy, Default, FromFormField)]
+pub enum DeathSort {
+    #[default]
+    Newest,
+    Oldest,
+    Damage,
+}
+
+const TOTAL_DAMAGE: &str = "(bruteloss + fireloss + oxyloss + toxloss)";
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeathCursor {
+    pub damage: Option<i64>,
+    pub id: i64,
+}
+
+impl DeathCursor {
+    pub fn parse(cursor: &str, sort: DeathSort) -> Option<Self> {
+        match sort {
+            DeathSort::Damage => {
+                let (damage, id) = cursor.split_once('_')?;
+
+                Some(DeathCursor {
+                    damage: Some(damage.parse().ok()?),
+                    id: id.parse().ok()?,
+                })
+            }
+            _ => Some(DeathCursor {
+                damage: None,
+                id: cursor.parse().ok()?,
+            }),
+        }
     }
 
-    let mut query = sqlx::query_scalar(&sql);
+    pub fn of(death: &Death, sort: DeathSort) -> String {
+        match sort {
+            DeathSort::Damage => format!("{}_{}", death.total_damage(), death.id),
+            _ => death.id.to_string(),
+        }
+    }
+}
 
-    if let Some(round_id) = round_id {
-        query = query.bind(round_id);
+#[derive(Debug, Default)]
+pub struct DeathFilter<'a> {
+    pub round_id: Option<u32>,
+    pub job: Option<&'a str>,
+    pub special: Option<&'a str>,
+    pub pod: Option<&'a str>,
+    pub suicide: Option<bool>,
+    pub damage: Option<DamageType>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub ckey: Option<&'a Ckey>,
+}
+
+impl DeathFilter<'_> {
+    // the damage sort orders by a sum no index covers, so it needs a round or dates to scan
+    pub fn is_narrowed(&self) -> bool {
+        self.round_id.is_some() || self.from.is_some() || self.to.is_some()
     }
 
-    let total_count = query.fetch_one(&mut *connection).await?;
+    // every condition is prefixed with AND, so this goes after a WHERE on the live round
+    fn push_sql(&self, sql: &mut String) {
+        if self.round_id.is_some() {
+            sql.push_str(" AND round_id = ?");
+        }
+
+        if self.job.is_some() {
+            sql.push_str(" AND job = ?");
+        }
 
-    let mut sql = "SELECT name, job, pod, bruteloss, fireloss, oxyloss, toxloss, last_words, suicide, round_id, tod FROM death".to_string();
+        if self.special.is_some() {
+            sql.push_str(" AND special = ?");
+        }
 
-    if round_id.is_some() {
-        sql.push_str(" WHERE round_id < ?");
+        if self.pod.is_some() {
+            sql.push_str(" AND pod LIKE ?");
+        }
+
+        if self.suicide.is_some() {
+            sql.push_str(" AND suicide = ?");
+        }
+
+        if let Some(damage) = self.damage {
+            sql.push_str(damage.as_sql());
+        }
+
+        if self.from.is_some() {
+            sql.push_str(" AND tod >= ?");
+        }
+
+        if self.to.is_some() {
+            sql.push_str(" AND tod < DATE_ADD(?, INTERVAL 1 DAY)");
+        }
+
+        if self.ckey.is_some() {
+            sql.push_str(&format!(" AND {DEATH_BY_CKEY}"));
+        }
     }
 
-    sql.push_str(" ORDER BY tod DESC LIMIT ? OFFSET ?");
+    fn bind<'q>(
+        &self,
+        mut query: Query<'q, MySql, MySqlArguments>,
+    ) -> Query<'q, MySql, MySqlArguments>
+    where
+        Self: 'q,
+    {
+        if let Some(round_id) = self.round_id {
+            query = query.bind(round_id);
+        }
 
-    let mut query = sqlx::query(&sql);
+        if let Some(job) = self.job {
+            query = query.bind(job);
+        }
 
-    if let Some(round_id) = round_id {
-        query = query.bind(round_id);
+        if let Some(special) = self.special {
+            query = query.bind(special);
+        }
+
+        if let Some(pod) = self.pod {
+            query = query.bind(format!("%{}%", escape_like(pod)));
+        }
+
+        if let Some(suicide) = self.suicide {
+            query = query.bind(suicide);
+        }
+
+        if let Some(from) = self.from {
+            query = query.bind(from);
+        }
+
+        if let Some(to) = self.to {
+            query = query.bind(to);
+        }
+
+        if let Some(ckey) = self.ckey {
+            query = query.bind(ckey.as_str()).bind(ckey.as_str());
+        }
+
+        query
     }
+}
 
-    query = query.bind(fetch_size).bind(offset);
+pub async fn get_deaths(
+    filter: &DeathFilter<'_>,
+    sort: DeathSort,
+    after: Option<DeathCursor>,
+    limit: u32,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<Vec<Death>, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = format!("SELECT {DEATH_COLUMNS} FROM death WHERE round_id < ?");
+
+    filter.push_sql(&mut sql);
+
+    match (sort, after) {
+        (DeathSort::Newest, Some(_)) => sql.push_str(" AND id < ?"),
+        (DeathSort::Oldest, Some(_)) => sql.push_str(" AND id > ?"),
+        (DeathSort::Damage, Some(_)) => sql.push_str(&format!(
+            " AND ({TOTAL_DAMAGE} < ? OR ({TOTAL_DAMAGE} = ? AND id < ?))"
+        )),
+        (_, None) => {}
+    }
+
+    match sort {
+        DeathSort::Newest => sql.push_str(" ORDER BY id DESC"),
+        DeathSort::Oldest => sql.push_str(" ORDER BY id ASC"),
+        DeathSort::Damage => sql.push_str(&format!(" ORDER BY {TOTAL_DAMAGE} DESC, id DESC")),
+    }
+
+    sql.push_str(" LIMIT ?");
+
+    let mut query = filter.bind(sqlx::query(&sql).bind(bound));
+
+    if let Some(after) = after {
+        if let Some(damage) = after.damage {
+            query = query.bind(damage).bind(damage);
+        }
+
+        query = query.bind(after.id);
+    }
+
+    query = query.bind(limit);
 
     let mut deaths = Vec::new();
 
@@ -132,29 +338,36 @@ pub async fn get_deaths(
         let mut rows = connection.fetch(query);
 
         while let Some(row) = rows.next().await {
-            let death = row?;
-
-            let death = Death {
-                name: death.try_get("name")?,
-                job: death.try_get("job")?,
-                pod: death.try_get("pod")?,
-                bruteloss: death.try_get("bruteloss")?,
-                fireloss: death.try_get("fireloss")?,
-                oxyloss: death.try_get("oxyloss")?,
-                toxloss: death.try_get("toxloss")?,
-                last_words: death.try_get("last_words")?,
-                suicide: death.try_get("suicide")?,
-                round_id: death.try_get("round_id")?,
-                tod: death.try_get("tod")?,
-            };
-
-            deaths.push(death);
+            deaths.push(Death::from_row(&row?)?);
         }
     }
 
     connection.close().await?;
 
-    Ok((deaths, total_count))
+    Ok(deaths)
+}
+
+// counting scans every matching row, so it is only done when asked for
+pub async fn count_deaths(
+    filter: &DeathFilter<'_>,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<i64, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = "SELECT COUNT(*) FROM death WHERE round_id < ?".to_string();
+
+    filter.push_sql(&mut sql);
+
+    let query = filter.bind(sqlx::query(&sql).bind(bound));
+
+    let total_count = connection.fetch_one(query).await?.try_get(0)?;
+
+    connection.close().await?;
+
+    Ok(total_count)
 }
 
 #[derive(Debug, Serialize)]
@@ -168,6 +381,19 @@ pub struct Citation {
     pub timestamp: NaiveDateTime,
 }
 
+impl Citation {
+    pub(super) fn from_row(citation: &MySqlRow) -> Result<Self, Error> {
+        Ok(Citation {
+            round_id: citation.try_get("round_id")?,
+            sender: citation.try_get("sender_ic")?,
+            recipient: citation.try_get("recipient")?,
+            crime: citation.try_get("crime")?,
+            fine: citation.try_get("fine")?,
+            timestamp: citation.try_get("timestamp")?,
+        })
+    }
+}
+
 pub async fn get_citations(
     fetch_size: Option<i32>,
     page: Option<i32>,
@@ -219,18 +445,7 @@ pub async fn get_citations(
         let mut rows = connection.fetch(query);
 
         while let Some(row) = rows.next().await {
-            let citation = row?;
-
-            let citation = Citation {
-                round_id: citation.try_get("round_id")?,
-                sender: citation.try_get("sender_ic")?,
-                recipient: citation.try_get("recipient")?,
-                crime: citation.try_get("crime")?,
-                fine: citation.try_get("fine")?,
-                timestamp: citation.try_get("timestamp")?,
-            };
-
-            citations.push(citation);
+            citations.push(Citation::from_row(&row?)?);
         }
     }
 
@@ -365,6 +580,22 @@ pub async fn get_rounds_overview(
     Ok(durations)
 }
 
+// total players from a round_end_stats feedback
+pub(super) fn parse_players(round_end_stats: &Value) -> Option<u64> {
+    round_end_stats["data"]["players"]["total"].as_u64()
+}
+
+// threat level and readied players from a dynamic_threat feedback
+pub(super) fn parse_threat(dynamic_threat: &Value) -> (Option<i32>, Option<i32>) {
+    let parse = |key: &str| {
+        dynamic_threat["data"]["1"][key]
+            .as_str()
+            .and_then(|s| s.parse::<i32>().ok())
+    };
+
+    (parse("threat_level"), parse("player_count"))
+}
+
 pub async fn get_players_overview(
     limit: i32,
     exclude_round: Option<i32>,
@@ -382,9 +613,7 @@ pub async fn get_players_overview(
     let mut players = HashMap::new();
 
     for feedback in &feedback {
-        let players_ = feedback.json["data"]["players"]["total"]
-            .as_u64()
-            .unwrap_or(0);
+        let players_ = parse_players(&feedback.json).unwrap_or(0);
 
         if let Some(round_id) = feedback.round_id {
             players.insert(round_id, players_ as u32);
@@ -411,18 +640,13 @@ pub async fn get_threat_overview(
     let mut threats = HashMap::new();
 
     for feedback in &feedback {
-        let threat_level = feedback.json["data"]["1"]["threat_level"]
-            .as_str()
-            .and_then(|s| s.parse::<i32>().ok())
-            .unwrap_or(0);
-
-        let readied_players = feedback.json["data"]["1"]["player_count"]
-            .as_str()
-            .and_then(|s| s.parse::<i32>().ok())
-            .unwrap_or(0);
+        let (threat_level, readied_players) = parse_threat(&feedback.json);
 
         if let Some(round_id) = feedback.round_id {
-            threats.insert(round_id, (threat_level, readied_players));
+            threats.insert(
+                round_id,
+                (threat_level.unwrap_or(0), readied_players.unwrap_or(0)),
+            );
         }
     }
 
@@ -499,3 +723,59 @@ pub async fn get_round_id(config: &Config) -> Result<Option<i32>, Error> {
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn death(id: i64, damage: [u16; 4]) -> Death {
+        Death {
+            id,
+            name: "John Doe".to_string(),
+            job: "Assistant".to_string(),
+            special: None,
+            pod: "Maintenance".to_string(),
+            bruteloss: damage[0],
+            fireloss: damage[1],
+            oxyloss: damage[2],
+            toxloss: damage[3],
+            last_words: None,
+            suicide: false,
+            round_id: Some(1),
+            tod: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn id_cursor_round_trips() {
+        let death = death(42, [10, 0, 0, 0]);
+
+        for sort in [DeathSort::Newest, DeathSort::Oldest] {
+            let cursor = DeathCursor::of(&death, sort);
+            assert_eq!(cursor, "42");
+
+            let parsed = DeathCursor::parse(&cursor, sort).unwrap();
+            assert_eq!((parsed.damage, parsed.id), (None, 42));
+        }
+    }
+
+    #[test]
+    fn damage_cursor_round_trips() {
+        let death = death(42, [100, 50, 25, 25]);
+
+        let cursor = DeathCursor::of(&death, DeathSort::Damage);
+        assert_eq!(cursor, "200_42");
+
+        let parsed = DeathCursor::parse(&cursor, DeathSort::Damage).unwrap();
+        assert_eq!((parsed.damage, parsed.id), (Some(200), 42));
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        assert!(DeathCursor::parse("abc", DeathSort::Newest).is_none());
+        assert!(DeathCursor::parse("200_42", DeathSort::Newest).is_none());
+        assert!(DeathCursor::parse("42", DeathSort::Damage).is_none());
+        assert!(DeathCursor::parse("x_42", DeathSort::Damage).is_none());
+        assert!(DeathCursor::parse("200_", DeathSort::Damage).is_none());
+    }
+}
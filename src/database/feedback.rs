@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sqlx::{mysql::MySqlArguments, query::Query, Executor as _, MySql, MySqlPool, Row as _};
+
+use crate::config::Config;
+
+use super::{error::Error, round_bound, Feedback};
+
+#[derive(Debug, Serialize)]
+pub struct FeedbackKey {
+    pub key_name: String,
+    pub key_type: String,
+    pub entries: i64,
+    pub last_round: Option<u32>,
+}
+
+pub async fn get_feedback_keys(
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<Vec<FeedbackKey>, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let query = sqlx::query(
+        "SELECT key_name, key_type, COUNT(*) AS entries, MAX(round_id) AS last_round FROM feedback WHERE round_id < ? GROUP BY key_name, key_type ORDER BY key_name ASC",
+    )
+    .bind(bound);
+
+    let mut keys = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            keys.push(FeedbackKey {
+                key_name: row.try_get("key_name")?,
+                key_type: row.try_get("key_type")?,
+                entries: row.try_get("entries")?,
+                last_round: row.try_get("last_round")?,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(keys)
+}
+
+#[derive(Debug, Default)]
+pub struct FeedbackFilter<'a> {
+    pub key_name: &'a str,
+    pub key_type: Option<&'a str>,
+    pub from_round: Option<u32>,
+    pub to_round: Option<u32>,
+}
+
+impl FeedbackFilter<'_> {
+    // every condition is prefixed with AND, so this goes after a WHERE on the live round
+    fn push_sql(&self, sql: &mut String) {
+        sql.push_str(" AND key_name = ?");
+
+        if self.key_type.is_some() {
+            sql.push_str(" AND key_type = ?");
+        }
+
+        if self.from_round.is_some() {
+            sql.push_str(" AND round_id >= ?");
+        }
+
+        if self.to_round.is_some() {
+            sql.push_str(" AND round_id <= ?");
+        }
+    }
+
+    fn bind<'q>(
+        &self,
+        mut query: Query<'q, MySql, MySqlArguments>,
+    ) -> Query<'q, MySql, MySqlArguments>
+    where
+        Self: 'q,
+    {
+        query = query.bind(self.key_name);
+
+        if let Some(key_type) = self.key_type {
+            query = query.bind(key_type);
+        }
+
+        if let Some(from_round) = self.from_round {
+            query = query.bind(from_round);
+        }
+
+        if let Some(to_round) = self.to_round {
+            query = query.bind(to_round);
+        }
+
+        query
+    }
+}
+
+pub async fn search_feedback(
+    filter: &FeedbackFilter<'_>,
+    limit: u32,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<Vec<Feedback>, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let mut sql =
+        "SELECT datetime, round_id, key_name, key_type, json FROM feedback WHERE round_id < ?"
+            .to_string();
+
+    filter.push_sql(&mut sql);
+
+    sql.push_str(" ORDER BY round_id DESC LIMIT ?");
+
+    let query = filter.bind(sqlx::query(&sql).bind(bound)).bind(limit);
+
+    let mut feedbacks = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let feedback = row?;
+
+            feedbacks.push(Feedback {
+                datetime: feedback.try_get("datetime")?,
+                round_id: feedback.try_get("round_id")?,
+                key_name: feedback.try_get("key_name")?,
+                key_type: feedback.try_get("key_type")?,
+                json: feedback.try_get("json")?,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(feedbacks)
+}
+
+// adds numeric leaves together, keeping the shape of the nested objects
+fn merge_tally(into: &mut Map<String, Value>, from: &Map<String, Value>) {
+    for (key, value) in from {
+        match (into.get_mut(key), value) {
+            (Some(Value::Object(into)), Value::Object(from)) => merge_tally(into, from),
+            (Some(existing), value) => {
+                if let (Some(a), Some(b)) = (existing.as_f64(), value.as_f64()) {
+                    *existing = json!(a + b);
+                }
+            }
+            (None, value) => {
+                into.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+// combines entries the way their key_type is recorded in game
+enum Aggregate {
+    Amount(f64),
+    Text(BTreeMap<String, u64>),
+    Tally(Map<String, Value>),
+    // each entry is a list of key/value records, values are counted per key
+    Associative(BTreeMap<String, BTreeMap<String, u64>>),
+}
+
+impl Aggregate {
+    fn new(key_type: &str) -> Option<Self> {
+        match key_type {
+            "amount" => Some(Self::Amount(0.0)),
+            "text" => Some(Self::Text(BTreeMap::new())),
+            "tally" | "nested tally" => Some(Self::Tally(Map::new())),
+            "associative" => Some(Self::Associative(BTreeMap::new())),
+            _ => None,
+        }
+    }
+
+    fn add(&mut self, data: &Value) {
+        match self {
+            Self::Amount(sum) => *sum += data.as_f64().unwrap_or(0.0),
+            Self::Text(counts) => {
+                for text in data.as_array().into_iter().flatten() {
+                    *counts.entry(value_text(text)).or_default() += 1;
+                }
+            }
+            Self::Tally(merged) => {
+                if let Some(data) = data.as_object() {
+                    merge_tally(merged, data);
+                }
+            }
+            Self::Associative(counts) => {
+                let records = data.as_object().into_iter().flat_map(Map::values);
+
+                for (key, value) in records.filter_map(Value::as_object).flatten() {
+                    *counts
+                        .entry(key.clone())
+                        .or_default()
+                        .entry(value_text(value))
+                        .or_default() += 1;
+                }
+            }
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Self::Amount(sum) => json!(sum),
+            Self::Text(counts) => json!(counts),
+            Self::Tally(merged) => Value::Object(merged),
+            Self::Associative(counts) => json!(counts),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedbackAggregate {
+    pub entries: u64,
+    pub value: Value,
+}
+
+// runs over the whole filtered range rather than a page of it, entries are folded as they stream in
+pub async fn aggregate_feedback(
+    filter: &FeedbackFilter<'_>,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<Option<FeedbackAggregate>, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = "SELECT key_type, json FROM feedback WHERE round_id < ?".to_string();
+
+    filter.push_sql(&mut sql);
+
+    let query = filter.bind(sqlx::query(&sql).bind(bound));
+
+    let mut aggregate: Option<(String, Aggregate)> = None;
+    let mut entries = 0;
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let key_type: String = row.try_get("key_type")?;
+            let json: Value = row.try_get("json")?;
+
+            let (aggregate_type, aggregate) = match &mut aggregate {
+                Some(aggregate) => aggregate,
+                None => {
+                    let Some(new) = Aggregate::new(&key_type) else {
+                        return Err(Error::FeedbackNotAggregatable);
+                    };
+
+                    aggregate.insert((key_type.clone(), new))
+                }
+            };
+
+            // keys recorded under several types can't be combined without picking one
+            if *aggregate_type != key_type {
+                return Err(Error::FeedbackNotAggregatable);
+            }
+
+            aggregate.add(&json["data"]);
+            entries += 1;
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(aggregate.map(|(_, aggregate)| FeedbackAggregate {
+        entries,
+        value: aggregate.into_value(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregate(key_type: &str, entries: &[Value]) -> Value {
+        let mut aggregate = Aggregate::new(key_type).unwrap();
+
+        for data in entries {
+            aggregate.add(data);
+        }
+
+        aggregate.into_value()
+    }
+
+    #[test]
+    fn merges_nested_tallies() {
+        let mut into = json!({"a": 1, "b": {"c": 2}}).as_object().unwrap().clone();
+        let from = json!({"a": 2, "b": {"c": 3, "d": 1}, "e": 4});
+
+        merge_tally(&mut into, from.as_object().unwrap());
+
+        assert_eq!(
+            Value::Object(into),
+            json!({"a": 3.0, "b": {"c": 5.0, "d": 1}, "e": 4})
+        );
+    }
+
+    #[test]
+    fn sums_amounts() {
+        assert_eq!(
+            aggregate("amount", &[json!(2), json!(3.5), json!(null)]),
+            json!(5.5)
+        );
+    }
+
+    #[test]
+    fn counts_text() {
+        let entries = [json!(["a", "b"]), json!(["a", 1])];
+
+        assert_eq!(aggregate("text", &entries), json!({"1": 1, "a": 2, "b": 1}));
+    }
+
+    #[test]
+    fn merges_tally_entries() {
+        let entries = [json!({"x": 1}), json!({"x": 2, "y": 1})];
+
+        assert_eq!(aggregate("tally", &entries), json!({"x": 3.0, "y": 1}));
+        assert_eq!(
+            aggregate(
+                "nested tally",
+                &[json!({"x": {"y": 1}}), json!({"x": {"y": 1}})]
+            ),
+            json!({"x": {"y": 2.0}})
+        );
+    }
+
+    #[test]
+    fn counts_associative_values_per_key() {
+        let entries = [
+            json!({"1": {"map": "Box", "mode": "extended"}, "2": {"map": "Meta"}}),
+            json!({"1": {"map": "Box", "mode": 5}}),
+        ];
+
+        assert_eq!(
+            aggregate("associative", &entries),
+            json!({
+                "map": {"Box": 2, "Meta": 1},
+                "mode": {"5": 1, "extended": 1},
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_types_are_not_aggregated() {
+        assert!(Aggregate::new("unknown").is_none());
+    }
+}
@@ -0,0 +1,153 @@
+use rocket::{futures::StreamExt as _, FromFormField};
+use serde::Serialize;
+use sqlx::{mysql::MySqlArguments, query::Query, Executor as _, MySql, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::error::Error;
+
+#[derive(Debug, Clone, Copy, Default, FromFormField)]
+pub enum LeaderboardPeriod {
+    #[field(value = "7d")]
+    Week,
+    #[field(value = "30d")]
+    Month,
+    #[field(value = "90d")]
+    Quarter,
+    #[default]
+    All,
+}
+
+impl LeaderboardPeriod {
+    fn days(&self) -> Option<u32> {
+        match self {
+            LeaderboardPeriod::Week => Some(7),
+            LeaderboardPeriod::Month => Some(30),
+            LeaderboardPeriod::Quarter => Some(90),
+            LeaderboardPeriod::All => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: u64,
+    pub ckey: String,
+    pub minutes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+    pub total: i64,
+    pub player: Option<LeaderboardEntry>,
+}
+
+// all-time totals live in role_time, windowed ones have to be summed from the deltas
+fn totals_sql(jobs: &[String], period: LeaderboardPeriod) -> String {
+    let placeholders = vec!["?"; jobs.len()].join(", ");
+
+    match period.days() {
+        Some(_) => format!(
+            "SELECT ckey, CAST(SUM(delta) AS SIGNED) AS minutes FROM role_time_log WHERE LOWER(job) IN ({placeholders}) AND datetime >= NOW() - INTERVAL ? DAY GROUP BY ckey HAVING minutes > 0"
+        ),
+        None => format!(
+            "SELECT ckey, CAST(SUM(minutes) AS SIGNED) AS minutes FROM role_time WHERE LOWER(job) IN ({placeholders}) GROUP BY ckey HAVING minutes > 0"
+        ),
+    }
+}
+
+fn bind_totals<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    jobs: &[String],
+    period: LeaderboardPeriod,
+) -> Query<'q, MySql, MySqlArguments> {
+    for job in jobs {
+        query = query.bind(job.to_lowercase());
+    }
+
+    if let Some(days) = period.days() {
+        query = query.bind(days);
+    }
+
+    query
+}
+
+pub async fn get_leaderboard(
+    jobs: &[String],
+    period: LeaderboardPeriod,
+    offset: u64,
+    limit: u32,
+    ckey: Option<&Ckey>,
+    pool: &MySqlPool,
+) -> Result<Leaderboard, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let totals = totals_sql(jobs, period);
+
+    let sql = format!("{totals} ORDER BY minutes DESC, ckey ASC LIMIT ? OFFSET ?");
+    let query = bind_totals(sqlx::query(&sql), jobs, period)
+        .bind(limit)
+        .bind(offset);
+
+    let mut entries = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            entries.push(LeaderboardEntry {
+                rank: offset + entries.len() as u64 + 1,
+                ckey: row.try_get("ckey")?,
+                minutes: row.try_get("minutes")?,
+            });
+        }
+    }
+
+    let sql = format!("SELECT COUNT(*) FROM ({totals}) AS totals");
+    let query = bind_totals(sqlx::query(&sql), jobs, period);
+
+    let total: i64 = connection.fetch_one(query).await?.try_get(0)?;
+
+    let player = match ckey {
+        Some(ckey) => {
+            let sql = format!("SELECT minutes FROM ({totals}) AS totals WHERE ckey = ?");
+            let query = bind_totals(sqlx::query(&sql), jobs, period).bind(ckey.as_str());
+
+            match connection.fetch_optional(query).await? {
+                Some(row) => {
+                    let minutes: i64 = row.try_get("minutes")?;
+
+                    // ties are broken by ckey, same as the ordering above
+                    let sql = format!(
+                        "SELECT COUNT(*) FROM ({totals}) AS totals WHERE minutes > ? OR (minutes = ? AND ckey < ?)"
+                    );
+                    let query = bind_totals(sqlx::query(&sql), jobs, period)
+                        .bind(minutes)
+                        .bind(minutes)
+                        .bind(ckey.as_str());
+
+                    let ahead: i64 = connection.fetch_one(query).await?.try_get(0)?;
+
+                    Some(LeaderboardEntry {
+                        rank: ahead as u64 + 1,
+                        ckey: ckey.to_string(),
+                        minutes,
+                    })
+                }
+                None => None,
+            }
+        }
+        None => None,
+    };
+
+    connection.close().await?;
+
+    Ok(Leaderboard {
+        entries,
+        total,
+        player,
+    })
+}
@@ -0,0 +1,192 @@
+use chrono::NaiveDateTime;
+use rocket::{futures::StreamExt as _, FromFormField};
+use serde::Serialize;
+use sqlx::{mysql::MySqlRow, Executor as _, MySqlPool, Row as _};
+
+use super::{error::Error, escape_like};
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum BookCategory {
+    Any,
+    Fiction,
+    #[field(value = "non-fiction")]
+    #[field(value = "nonfiction")]
+    NonFiction,
+    Adult,
+    Reference,
+    Religion,
+}
+
+impl BookCategory {
+    // the game only uses "Any" as a search wildcard, no book is stored with it
+    fn as_sql(&self) -> Option<&'static str> {
+        match self {
+            BookCategory::Any => None,
+            BookCategory::Fiction => Some("Fiction"),
+            BookCategory::NonFiction => Some("Non-Fiction"),
+            BookCategory::Adult => Some("Adult"),
+            BookCategory::Reference => Some("Reference"),
+            BookCategory::Religion => Some("Religion"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Book {
+    pub id: u32,
+    pub title: String,
+    pub author: String,
+    pub category: String,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub datetime: Option<NaiveDateTime>,
+    pub round_id_created: u32,
+    // uploader and deletion state are left out for scoped keys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ckey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl Book {
+    fn from_row(row: &MySqlRow, privileged: bool) -> Result<Self, Error> {
+        let (ckey, deleted) = match privileged {
+            true => {
+                let deleted: Option<u8> = row.try_get("deleted")?;
+                (
+                    Some(row.try_get("ckey")?),
+                    Some(deleted.is_some_and(|deleted| deleted != 0)),
+                )
+            }
+            false => (None, None),
+        };
+
+        Ok(Book {
+            id: row.try_get("id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            category: row.try_get("category")?,
+            datetime: row.try_get("datetime")?,
+            round_id_created: row.try_get("round_id_created")?,
+            ckey,
+            deleted,
+            content: None,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BookFilter<'a> {
+    pub search: Option<&'a str>,
+    pub title: Option<&'a str>,
+    pub author: Option<&'a str>,
+    pub category: Option<BookCategory>,
+    pub include_deleted: bool,
+}
+
+pub async fn get_books(
+    filter: &BookFilter<'_>,
+    privileged: bool,
+    after: Option<u32>,
+    limit: u32,
+    pool: &MySqlPool,
+) -> Result<Vec<Book>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = "SELECT id, title, author, category, datetime, round_id_created, ckey, deleted FROM library WHERE 1 = 1".to_string();
+
+    if !filter.include_deleted {
+        sql.push_str(" AND (deleted IS NULL OR deleted = 0)");
+    }
+
+    if filter.search.is_some() {
+        sql.push_str(" AND (title LIKE ? OR author LIKE ?)");
+    }
+
+    if filter.title.is_some() {
+        sql.push_str(" AND title LIKE ?");
+    }
+
+    if filter.author.is_some() {
+        sql.push_str(" AND author LIKE ?");
+    }
+
+    let category = filter.category.and_then(|category| category.as_sql());
+
+    if category.is_some() {
+        sql.push_str(" AND category = ?");
+    }
+
+    if after.is_some() {
+        sql.push_str(" AND id < ?");
+    }
+
+    sql.push_str(" ORDER BY id DESC LIMIT ?");
+
+    let mut query = sqlx::query(&sql);
+
+    if let Some(search) = filter.search {
+        let pattern = format!("%{}%", escape_like(search));
+        query = query.bind(pattern.clone()).bind(pattern);
+    }
+
+    if let Some(title) = filter.title {
+        query = query.bind(format!("%{}%", escape_like(title)));
+    }
+
+    if let Some(author) = filter.author {
+        query = query.bind(format!("%{}%", escape_like(author)));
+    }
+
+    if let Some(category) = category {
+        query = query.bind(category);
+    }
+
+    if let Some(after) = after {
+        query = query.bind(after);
+    }
+
+    query = query.bind(limit);
+
+    let mut books = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            books.push(Book::from_row(&row?, privileged)?);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(books)
+}
+
+pub async fn get_book(id: u32, privileged: bool, pool: &MySqlPool) -> Result<Option<Book>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = "SELECT id, title, author, category, datetime, round_id_created, ckey, deleted, content FROM library WHERE id = ?".to_string();
+
+    if !privileged {
+        sql.push_str(" AND (deleted IS NULL OR deleted = 0)");
+    }
+
+    let query = sqlx::query(&sql).bind(id);
+
+    let Some(row) = connection.fetch_optional(query).await? else {
+        connection.close().await?;
+        return Ok(None);
+    };
+
+    let mut book = Book::from_row(&row, privileged)?;
+
+    // books are written in-game with arbitrary html, so only a safe subset is passed on
+    let content: String = row.try_get("content")?;
+    book.content = Some(ammonia::clean(&content));
+
+    connection.close().await?;
+
+    Ok(Some(book))
+}
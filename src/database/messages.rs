@@ -0,0 +1,173 @@
+use chrono::NaiveDateTime;
+use rocket::{futures::StreamExt as _, FromFormField};
+use serde::Serialize;
+use sqlx::{Executor as _, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::{error::Error, player_exists};
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum MessageType {
+    Memo,
+    Message,
+    #[field(value = "message sent")]
+    #[field(value = "message_sent")]
+    MessageSent,
+    Note,
+    #[field(value = "watchlist entry")]
+    #[field(value = "watchlist_entry")]
+    WatchlistEntry,
+}
+
+impl MessageType {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            MessageType::Memo => "memo",
+            MessageType::Message => "message",
+            MessageType::MessageSent => "message sent",
+            MessageType::Note => "note",
+            MessageType::WatchlistEntry => "watchlist entry",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum Severity {
+    High,
+    Medium,
+    Minor,
+    None,
+}
+
+impl Severity {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Severity::High => "high",
+            Severity::Medium => "medium",
+            Severity::Minor => "minor",
+            Severity::None => "none",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MessageFilter {
+    pub message_type: Option<MessageType>,
+    pub severity: Option<Severity>,
+    pub secret: Option<bool>,
+    pub include_secret: bool,
+    pub include_expired: bool,
+    pub include_deleted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub targetckey: String,
+    pub adminckey: String,
+    pub text: String,
+    #[serde(with = "crate::serde::datetime")]
+    pub timestamp: NaiveDateTime,
+    pub server: Option<String>,
+    pub round_id: Option<u32>,
+    pub secret: bool,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub expire_timestamp: Option<NaiveDateTime>,
+    pub severity: Option<String>,
+    pub playtime: Option<u32>,
+    pub lasteditor: Option<String>,
+    pub edits: Option<String>,
+    pub deleted: bool,
+    pub deleted_ckey: Option<String>,
+}
+
+pub async fn get_messages(
+    ckey: &Ckey,
+    filter: &MessageFilter,
+    pool: &MySqlPool,
+) -> Result<Vec<Message>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = "SELECT id, type, targetckey, adminckey, text, timestamp, server, round_id, secret, expire_timestamp, severity, playtime, lasteditor, edits, deleted, deleted_ckey FROM messages WHERE targetckey = ?".to_string();
+
+    if filter.message_type.is_some() {
+        sql.push_str(" AND type = ?");
+    }
+
+    if filter.severity.is_some() {
+        sql.push_str(" AND severity = ?");
+    }
+
+    if !filter.include_secret {
+        sql.push_str(" AND secret = 0");
+    } else if filter.secret.is_some() {
+        sql.push_str(" AND secret = ?");
+    }
+
+    if !filter.include_expired {
+        sql.push_str(" AND (expire_timestamp IS NULL OR expire_timestamp > NOW())");
+    }
+
+    if !filter.include_deleted {
+        sql.push_str(" AND deleted = 0");
+    }
+
+    sql.push_str(" ORDER BY timestamp DESC");
+
+    let mut query = sqlx::query(&sql).bind(ckey.as_str());
+
+    if let Some(message_type) = filter.message_type {
+        query = query.bind(message_type.as_sql());
+    }
+
+    if let Some(severity) = filter.severity {
+        query = query.bind(severity.as_sql());
+    }
+
+    if let (true, Some(secret)) = (filter.include_secret, filter.secret) {
+        query = query.bind(secret);
+    }
+
+    let mut messages = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let message = row?;
+
+            let message = Message {
+                id: message.try_get("id")?,
+                message_type: message.try_get("type")?,
+                targetckey: message.try_get("targetckey")?,
+                adminckey: message.try_get("adminckey")?,
+                text: message.try_get("text")?,
+                timestamp: message.try_get("timestamp")?,
+                server: message.try_get("server")?,
+                round_id: message.try_get("round_id")?,
+                secret: message.try_get("secret")?,
+                expire_timestamp: message.try_get("expire_timestamp")?,
+                severity: message.try_get("severity")?,
+                playtime: message.try_get("playtime")?,
+                lasteditor: message.try_get("lasteditor")?,
+                edits: message.try_get("edits")?,
+                deleted: message.try_get("deleted")?,
+                deleted_ckey: message.try_get("deleted_ckey")?,
+            };
+
+            messages.push(message);
+        }
+    }
+
+    if messages.is_empty() && !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    connection.close().await?;
+
+    Ok(messages)
+}
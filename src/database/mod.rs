@@ -1,12 +1,44 @@
+mod achievement;
+mod admin;
+mod ban;
+mod character;
 pub mod error;
 mod events;
+mod feedback;
+mod leaderboard;
+mod library;
+mod messages;
+mod patreon;
 mod player;
+mod poll;
+mod related;
+mod round;
 mod state;
+mod stickyban;
 mod test_merges;
 mod verify;
 
+pub use achievement::*;
+pub use admin::*;
+pub use ban::*;
+pub use character::*;
 pub use events::*;
+pub use feedback::*;
+pub use leaderboard::*;
+pub use library::*;
+pub use messages::*;
+pub use patreon::*;
 pub use player::*;
+pub use poll::*;
+pub use related::*;
+pub use round::*;
 pub use state::Database;
+pub use stickyban::*;
 pub use test_merges::*;
 pub use verify::*;
+
+pub fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
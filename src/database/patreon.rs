@@ -0,0 +1,125 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{pool::PoolConnection, Executor as _, MySql, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::{error::Error, player_exists};
+
+#[derive(Debug, Serialize)]
+pub struct Pledge {
+    pub patreon_id: String,
+    pub ckey: Option<String>,
+    pub full_name: Option<String>,
+    pub patron_status: Option<String>,
+    pub entitled_cents: u32,
+    pub tier_ids: Vec<String>,
+    pub last_event: String,
+    #[serde(with = "crate::serde::datetime")]
+    pub updated_at: NaiveDateTime,
+}
+
+impl Pledge {
+    pub fn is_active(&self) -> bool {
+        self.patron_status.as_deref() == Some("active_patron")
+    }
+}
+
+pub async fn record_pledge_event(pledge: &Pledge, pool: &MySqlPool) -> Result<(), Error> {
+    let mut connection = pool.acquire().await?;
+
+    let query = sqlx::query(
+        "INSERT INTO patreon_pledge (patreon_id, full_name, patron_status, entitled_cents, tier_ids, last_event, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?) ON DUPLICATE KEY UPDATE full_name = VALUES(full_name), patron_status = VALUES(patron_status), entitled_cents = VALUES(entitled_cents), tier_ids = VALUES(tier_ids), last_event = VALUES(last_event), updated_at = VALUES(updated_at)"
+    )
+    .bind(&pledge.patreon_id)
+    .bind(&pledge.full_name)
+    .bind(&pledge.patron_status)
+    .bind(pledge.entitled_cents)
+    .bind(pledge.tier_ids.join(","))
+    .bind(&pledge.last_event)
+    .bind(pledge.updated_at);
+
+    connection.execute(query).await?;
+    connection.close().await?;
+
+    Ok(())
+}
+
+pub async fn link_pledge(patreon_id: &str, ckey: &Ckey, pool: &MySqlPool) -> Result<(), Error> {
+    let mut connection = pool.acquire().await?;
+
+    if !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    match pledge_by_ckey(ckey, &mut connection).await {
+        Ok(pledge) if pledge.patreon_id != patreon_id => {
+            connection.close().await?;
+            return Err(Error::PledgeInUse(pledge.patreon_id));
+        }
+        Ok(_) | Err(Error::NotLinked) => {}
+        Err(e) => return Err(e),
+    }
+
+    // a pledge already linked to someone else stays with them
+    let query = sqlx::query(
+        "UPDATE patreon_pledge SET ckey = ? WHERE patreon_id = ? AND (ckey IS NULL OR ckey = ?)",
+    )
+    .bind(ckey.as_str())
+    .bind(patreon_id)
+    .bind(ckey.as_str());
+
+    let result = connection.execute(query).await?;
+
+    if result.rows_affected() == 0 {
+        let query =
+            sqlx::query("SELECT ckey FROM patreon_pledge WHERE patreon_id = ?").bind(patreon_id);
+
+        let linked = connection.fetch_optional(query).await?;
+
+        connection.close().await?;
+
+        return match linked {
+            Some(row) => Err(Error::PledgeLinked(row.try_get("ckey")?)),
+            None => Err(Error::PledgeNotFound),
+        };
+    }
+
+    connection.close().await?;
+
+    Ok(())
+}
+
+pub async fn pledge_by_ckey(
+    ckey: &Ckey,
+    connection: &mut PoolConnection<MySql>,
+) -> Result<Pledge, Error> {
+    let query = sqlx::query(
+        "SELECT patreon_id, ckey, full_name, patron_status, entitled_cents, tier_ids, last_event, updated_at FROM patreon_pledge WHERE ckey = ?"
+    )
+    .bind(ckey.as_str());
+
+    let Some(row) = connection.fetch_optional(query).await? else {
+        return Err(Error::NotLinked);
+    };
+
+    let tier_ids: String = row.try_get("tier_ids")?;
+
+    let pledge = Pledge {
+        patreon_id: row.try_get("patreon_id")?,
+        ckey: row.try_get("ckey")?,
+        full_name: row.try_get("full_name")?,
+        patron_status: row.try_get("patron_status")?,
+        entitled_cents: row.try_get("entitled_cents")?,
+        tier_ids: tier_ids
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect(),
+        last_event: row.try_get("last_event")?,
+        updated_at: row.try_get("updated_at")?,
+    };
+
+    Ok(pledge)
+}
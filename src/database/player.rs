@@ -1,10 +1,14 @@
-use chrono::{NaiveDate, NaiveDateTime};
-use const_format::concatcp;
-use rocket::futures::StreamExt as _;
+use chrono::{
+    Datelike as _, Duration, NaiveDate, NaiveDateTime, TimeZone as _, Timelike as _, Utc,
+};
+use chrono_tz::Tz;
+use rocket::{futures::StreamExt as _, FromFormField};
 use serde::Serialize;
-use sqlx::{pool::PoolConnection, Executor as _, FromRow, MySql, MySqlPool, Row as _};
+use sqlx::{pool::PoolConnection, Executor as _, MySql, MySqlPool, Row as _};
 
-use super::error::Error;
+use crate::ckey::Ckey;
+
+use super::{error::Error, DEATH_BY_CKEY};
 
 #[derive(Debug, Serialize)]
 pub struct Player {
@@ -20,13 +24,13 @@ pub struct Player {
     pub byond_age: Option<NaiveDate>,
 }
 
-pub async fn get_player(ckey: &str, pool: &MySqlPool) -> Result<Player, Error> {
+pub async fn get_player(ckey: &Ckey, pool: &MySqlPool) -> Result<Player, Error> {
     let mut connection = pool.acquire().await?;
 
     let query = sqlx::query(
-        "SELECT ckey, byond_key, firstseen, firstseen_round_id, lastseen, lastseen_round_id, INET_NTOA(ip), computerid, accountjoindate FROM player WHERE LOWER(ckey) = ?"
+        "SELECT ckey, byond_key, firstseen, firstseen_round_id, lastseen, lastseen_round_id, INET_NTOA(ip), computerid, accountjoindate FROM player WHERE ckey = ?"
     )
-    .bind(ckey.to_lowercase());
+    .bind(ckey.as_str());
 
     let Ok(row) = connection.fetch_one(query).await else {
         return Err(Error::PlayerNotFound);
@@ -85,17 +89,16 @@ pub async fn get_top_roletime(job: &str, pool: &MySqlPool) -> Result<Vec<JobRole
 
 #[derive(Debug, Serialize)]
 pub struct PlayerRoletime {
-    job: String,
-    minutes: u32,
+    pub job: String,
+    pub minutes: u32,
 }
 
-pub async fn get_roletime(ckey: &str, pool: &MySqlPool) -> Result<Vec<PlayerRoletime>, Error> {
+pub async fn get_roletime(ckey: &Ckey, pool: &MySqlPool) -> Result<Vec<PlayerRoletime>, Error> {
     let mut connection = pool.acquire().await?;
 
-    let query = sqlx::query(
-        "SELECT job, minutes FROM role_time WHERE LOWER(ckey) = ? ORDER BY minutes DESC",
-    )
-    .bind(ckey.to_lowercase());
+    let query =
+        sqlx::query("SELECT job, minutes FROM role_time WHERE ckey = ? ORDER BY minutes DESC")
+            .bind(ckey.as_str());
 
     let mut roletimes = Vec::new();
 
@@ -124,15 +127,85 @@ pub async fn get_roletime(ckey: &str, pool: &MySqlPool) -> Result<Vec<PlayerRole
     Ok(roletimes)
 }
 
-pub async fn get_jobs(job: &str, pool: &MySqlPool) -> Result<Vec<String>, Error> {
+#[derive(Debug, Clone, Copy, Default, FromFormField)]
+pub enum HistoryBucket {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl HistoryBucket {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            HistoryBucket::Day => "DATE_FORMAT(datetime, '%Y-%m-%d')",
+            // weeks start on monday
+            HistoryBucket::Week => {
+                "DATE_FORMAT(DATE_SUB(datetime, INTERVAL WEEKDAY(datetime) DAY), '%Y-%m-%d')"
+            }
+            HistoryBucket::Month => "DATE_FORMAT(datetime, '%Y-%m-01')",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoletimePoint {
+    pub date: String,
+    pub minutes: i64,
+    pub cumulative: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoletimeHistory {
+    pub job: String,
+    pub minutes: i64,
+    pub history: Vec<RoletimePoint>,
+}
+
+pub async fn get_roletime_history(
+    ckey: &Ckey,
+    job: Option<&str>,
+    bucket: HistoryBucket,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    pool: &MySqlPool,
+) -> Result<Vec<RoletimeHistory>, Error> {
     let mut connection = pool.acquire().await?;
 
-    let query = sqlx::query(
-        "SELECT DISTINCT job FROM role_time WHERE LOWER(job) LIKE ? ORDER BY job ASC LIMIT 25",
-    )
-    .bind(format!("%{}%", job.to_lowercase()));
+    let mut sql = format!(
+        "SELECT job, {} AS bucket, CAST(SUM(delta) AS SIGNED) AS minutes FROM role_time_log WHERE ckey = ?",
+        bucket.as_sql()
+    );
 
-    let mut jobs = Vec::new();
+    if job.is_some() {
+        sql.push_str(" AND LOWER(job) = ?");
+    }
+
+    if from.is_some() {
+        sql.push_str(" AND datetime >= ?");
+    }
+
+    if to.is_some() {
+        sql.push_str(" AND datetime < DATE_ADD(?, INTERVAL 1 DAY)");
+    }
+
+    sql.push_str(" GROUP BY job, bucket ORDER BY job ASC, bucket ASC");
+
+    let mut query = sqlx::query(&sql).bind(ckey.as_str());
+
+    if let Some(job) = job {
+        query = query.bind(job.to_lowercase());
+    }
+
+    if let Some(from) = from {
+        query = query.bind(from);
+    }
+
+    if let Some(to) = to {
+        query = query.bind(to);
+    }
+
+    let mut history: Vec<RoletimeHistory> = Vec::new();
 
     {
         let mut rows = connection.fetch(query);
@@ -140,122 +213,131 @@ pub async fn get_jobs(job: &str, pool: &MySqlPool) -> Result<Vec<String>, Error>
         while let Some(row) = rows.next().await {
             let row = row?;
 
-            let job = row.try_get("job")?;
+            let job: String = row.try_get("job")?;
+            let minutes: i64 = row.try_get("minutes")?;
+
+            // rows come ordered by job, so a new job always starts a new entry
+            let index = match history.last() {
+                Some(entry) if entry.job == job => history.len() - 1,
+                _ => {
+                    history.push(RoletimeHistory {
+                        job,
+                        minutes: 0,
+                        history: Vec::new(),
+                    });
+                    history.len() - 1
+                }
+            };
 
-            jobs.push(job);
+            let entry = &mut history[index];
+
+            entry.minutes += minutes;
+            entry.history.push(RoletimePoint {
+                date: row.try_get("bucket")?,
+                minutes,
+                cumulative: entry.minutes,
+            });
         }
     }
 
-    connection.close().await?;
+    // cumulative totals are all time, so add whatever was played before the window
+    if let Some(from) = from {
+        let mut sql = "SELECT job, CAST(SUM(delta) AS SIGNED) AS minutes FROM role_time_log WHERE ckey = ? AND datetime < ?".to_string();
 
-    Ok(jobs)
-}
+        if job.is_some() {
+            sql.push_str(" AND LOWER(job) = ?");
+        }
 
-pub async fn get_ckeys(ckey: &str, pool: &MySqlPool) -> Result<Vec<String>, Error> {
-    let mut connection = pool.acquire().await?;
+        sql.push_str(" GROUP BY job");
 
-    let query = sqlx::query("SELECT ckey FROM player WHERE ckey LIKE ? ORDER BY ckey LIMIT 25")
-        .bind(format!("{ckey}%"));
+        let mut query = sqlx::query(&sql).bind(ckey.as_str()).bind(from);
 
-    let mut ckeys = Vec::new();
+        if let Some(job) = job {
+            query = query.bind(job.to_lowercase());
+        }
 
-    {
         let mut rows = connection.fetch(query);
 
         while let Some(row) = rows.next().await {
             let row = row?;
 
-            let ckey = row.try_get("ckey")?;
+            let job: String = row.try_get("job")?;
+            let before: i64 = row.try_get("minutes")?;
 
-            ckeys.push(ckey);
+            if let Some(entry) = history.iter_mut().find(|entry| entry.job == job) {
+                for point in &mut entry.history {
+                    point.cumulative += before;
+                }
+            }
         }
     }
 
+    if history.is_empty() && !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
     connection.close().await?;
 
-    Ok(ckeys)
-}
+    history.sort_by_key(|entry| std::cmp::Reverse(entry.minutes));
 
-#[derive(Debug, Serialize)]
-pub struct Ban {
-    #[serde(with = "crate::serde::datetime")]
-    pub bantime: NaiveDateTime,
-    pub round_id: Option<u32>,
-    pub roles: Option<String>,
-    #[serde(with = "crate::serde::opt_datetime")]
-    pub expiration_time: Option<NaiveDateTime>,
-    pub reason: String,
-    pub ckey: Option<String>,
-    pub a_ckey: String,
-    pub edits: Option<String>,
-    #[serde(with = "crate::serde::opt_datetime")]
-    pub unbanned_datetime: Option<NaiveDateTime>,
-    pub unbanned_ckey: Option<String>,
+    Ok(history)
 }
 
-pub async fn get_ban(
-    ckey: &str,
-    permanent: bool,
-    since: Option<&str>,
-    pool: &MySqlPool,
-) -> Result<Vec<Ban>, Error> {
+pub async fn get_jobs(job: &str, pool: &MySqlPool) -> Result<Vec<String>, Error> {
     let mut connection = pool.acquire().await?;
 
-    let mut sql = "SELECT id, bantime, round_id, GROUP_CONCAT(role ORDER BY role SEPARATOR ', ') AS roles, expiration_time, reason, ckey, a_ckey, edits, unbanned_datetime, unbanned_ckey FROM ban WHERE LOWER(ckey) = ?".to_string();
+    let query = sqlx::query(
+        "SELECT DISTINCT job FROM role_time WHERE LOWER(job) LIKE ? ORDER BY job ASC LIMIT 25",
+    )
+    .bind(format!("%{}%", job.to_lowercase()));
 
-    if permanent {
-        sql.push_str(" AND expiration_time IS NULL");
-    }
+    let mut jobs = Vec::new();
 
-    if since.is_some() {
-        sql.push_str(" AND bantime > ?");
-    }
+    {
+        let mut rows = connection.fetch(query);
 
-    sql.push_str(" GROUP BY bantime");
+        while let Some(row) = rows.next().await {
+            let row = row?;
 
-    let mut query = sqlx::query(&sql).bind(ckey.to_lowercase());
+            let job = row.try_get("job")?;
 
-    if let Some(since) = since {
-        query = query.bind(since);
+            jobs.push(job);
+        }
     }
 
-    let mut bans = Vec::new();
+    connection.close().await?;
+
+    Ok(jobs)
+}
+
+pub async fn get_ckeys(ckey: &Ckey, pool: &MySqlPool) -> Result<Vec<String>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let query = sqlx::query("SELECT ckey FROM player WHERE ckey LIKE ? ORDER BY ckey LIMIT 25")
+        .bind(format!("{ckey}%"));
+
+    let mut ckeys = Vec::new();
 
     {
         let mut rows = connection.fetch(query);
 
         while let Some(row) = rows.next().await {
-            let ban = row?;
-
-            let ban = Ban {
-                bantime: ban.try_get("bantime")?,
-                round_id: ban.try_get("round_id")?,
-                roles: ban.try_get("roles")?,
-                expiration_time: ban.try_get("expiration_time")?,
-                reason: ban.try_get("reason")?,
-                ckey: ban.try_get("ckey")?,
-                a_ckey: ban.try_get("a_ckey")?,
-                edits: ban.try_get("edits")?,
-                unbanned_datetime: ban.try_get("unbanned_datetime")?,
-                unbanned_ckey: ban.try_get("unbanned_ckey")?,
-            };
+            let row = row?;
 
-            bans.push(ban);
-        }
-    }
+            let ckey = row.try_get("ckey")?;
 
-    if bans.is_empty() && !player_exists(ckey, &mut connection).await {
-        connection.close().await?;
-        return Err(Error::PlayerNotFound);
+            ckeys.push(ckey);
+        }
     }
 
     connection.close().await?;
 
-    Ok(bans)
+    Ok(ckeys)
 }
 
-pub async fn player_exists(ckey: &str, connection: &mut PoolConnection<MySql>) -> bool {
-    let query = sqlx::query("SELECT 1 FROM player WHERE LOWER(ckey) = ?").bind(ckey.to_lowercase());
+pub async fn player_exists(ckey: &Ckey, connection: &mut PoolConnection<MySql>) -> bool {
+    let query = sqlx::query("SELECT 1 FROM player WHERE ckey = ?").bind(ckey.as_str());
     connection.fetch_one(query).await.is_ok()
 }
 
@@ -293,17 +375,27 @@ pub async fn get_ic_names(ic_name: &str, pool: &MySqlPool) -> Result<Vec<IcName>
     Ok(ckeys)
 }
 
-pub async fn get_characters(ckey: &str, pool: &MySqlPool) -> Result<Vec<(String, i64)>, Error> {
+pub async fn get_characters(
+    ckey: &Ckey,
+    excluded_roles: &[String],
+    pool: &MySqlPool,
+) -> Result<Vec<(String, i64)>, Error> {
     let mut connection = pool.acquire().await?;
 
-    const EXCLUDED_ROLES: &str = "('Nightmare', 'Wizard', 'Nuclear Operative', 'Wizard (Midround)', 'Paradox Clone', 'Space Ninja', 'Fugitive', 'Syndicate Cyborg', 'Lone Operative', 'Maintenance Clown', 'Abductor', 'Operative (Midround)', 'Cyber Police', 'Syndicate Monkey Agent', 'apprentice', 'Glitch', 'Santa', 'Changeling', 'Changeling (Midround)', 'Syndicate Medical Cyborg', 'Operative Overwatch Agent', 'survivalist', 'Syndicate Assault Cyborg')";
+    let mut sql = format!("SELECT name, COUNT(*) AS times FROM death WHERE {DEATH_BY_CKEY}");
+
+    if !excluded_roles.is_empty() {
+        let placeholders = vec!["?"; excluded_roles.len()].join(", ");
+        sql.push_str(&format!(" AND special NOT IN ({placeholders})"));
+    }
+
+    sql.push_str(" GROUP BY name ORDER BY times DESC");
+
+    let mut query = sqlx::query(&sql).bind(ckey.as_str()).bind(ckey.as_str());
 
-    let query = sqlx::query(concatcp!(
-        "SELECT name, COUNT(*) AS times FROM death WHERE byondkey = ? AND special NOT IN ",
-        EXCLUDED_ROLES,
-        " GROUP BY name ORDER BY times DESC"
-    ))
-    .bind(ckey.to_lowercase());
+    for role in excluded_roles {
+        query = query.bind(role);
+    }
 
     let mut characters = Vec::new();
 
@@ -327,13 +419,44 @@ pub async fn get_characters(ckey: &str, pool: &MySqlPool) -> Result<Vec<(String,
     Ok(characters)
 }
 
-pub async fn get_activity(ckey: &str, pool: &MySqlPool) -> Result<Vec<(String, i64)>, Error> {
+// the activity window when no start date is requested, counted back from the end date or today
+const DEFAULT_ACTIVITY_DAYS: i64 = 180;
+
+fn activity_start(from: Option<NaiveDate>, to: Option<NaiveDate>) -> NaiveDate {
+    from.unwrap_or_else(|| {
+        to.unwrap_or_else(|| Utc::now().date_naive()) - Duration::days(DEFAULT_ACTIVITY_DAYS)
+    })
+}
+
+fn activity_range_sql(sql: &mut String, to: Option<NaiveDate>) {
+    sql.push_str(" AND datetime >= ?");
+
+    if to.is_some() {
+        sql.push_str(" AND datetime < DATE_ADD(?, INTERVAL 1 DAY)");
+    }
+}
+
+pub async fn get_activity(
+    ckey: &Ckey,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    pool: &MySqlPool,
+) -> Result<Vec<(String, i64)>, Error> {
     let mut connection = pool.acquire().await?;
 
-    let query = sqlx::query(
-        "SELECT DATE(datetime) AS date, COUNT(DISTINCT round_id) AS rounds FROM connection_log WHERE ckey = ? AND datetime >= DATE_SUB(CURDATE(), INTERVAL 180 DAY) GROUP BY date;"
-    )
-    .bind(ckey.to_lowercase());
+    let mut sql = "SELECT DATE(datetime) AS date, COUNT(DISTINCT round_id) AS rounds FROM connection_log WHERE ckey = ?".to_string();
+
+    activity_range_sql(&mut sql, to);
+
+    sql.push_str(" GROUP BY date ORDER BY date ASC");
+
+    let mut query = sqlx::query(&sql)
+        .bind(ckey.as_str())
+        .bind(activity_start(from, to));
+
+    if let Some(to) = to {
+        query = query.bind(to);
+    }
 
     let mut activity = Vec::new();
 
@@ -360,31 +483,87 @@ pub async fn get_activity(ckey: &str, pool: &MySqlPool) -> Result<Vec<(String, i
     Ok(activity)
 }
 
-#[derive(Debug, Serialize, FromRow)]
-pub struct Achievement {
-    pub achievement_key: String,
-    pub achievement_version: u16,
-    pub achievement_type: Option<String>,
-    pub achievement_name: Option<String>,
-    pub achievement_description: Option<String>,
-    pub value: Option<i32>,
-}
-
-pub async fn get_achievements(ckey: &str, pool: &MySqlPool) -> Result<Vec<Achievement>, Error> {
+// connections per weekday (monday first) and hour, shifted from the database timezone into the requested one
+pub async fn get_activity_heatmap(
+    ckey: Option<&Ckey>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    database_timezone: Tz,
+    timezone: Tz,
+    pool: &MySqlPool,
+) -> Result<[[i64; 24]; 7], Error> {
     let mut connection = pool.acquire().await?;
 
-    if !player_exists(ckey, &mut connection).await {
-        connection.close().await?;
-        return Err(Error::PlayerNotFound);
+    // bucketing by hour in sql keeps the result small, the timezone shift happens per bucket so dst is respected
+    let mut sql = "SELECT TIMESTAMP(DATE(datetime), MAKETIME(HOUR(datetime), 0, 0)) AS hour, COUNT(*) AS connections FROM connection_log WHERE 1 = 1".to_string();
+
+    if ckey.is_some() {
+        sql.push_str(" AND ckey = ?");
+    }
+
+    activity_range_sql(&mut sql, to);
+
+    sql.push_str(" GROUP BY hour");
+
+    let mut query = sqlx::query(&sql);
+
+    if let Some(ckey) = ckey {
+        query = query.bind(ckey.as_str());
+    }
+
+    query = query.bind(activity_start(from, to));
+
+    if let Some(to) = to {
+        query = query.bind(to);
     }
 
-    let query = sqlx::query_as(
-        "SELECT achievement_metadata.*, achievements.value FROM achievements JOIN achievement_metadata ON achievements.achievement_key = achievement_metadata.achievement_key WHERE LOWER(achievements.ckey) = ?"
-    ).bind(ckey.to_lowercase());
+    let mut heatmap = [[0; 24]; 7];
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let hour: NaiveDateTime = row.try_get("hour")?;
+            let connections: i64 = row.try_get("connections")?;
+
+            // hours skipped by a dst change can't have been written, repeated ones take the first
+            let Some(hour) = database_timezone.from_local_datetime(&hour).earliest() else {
+                continue;
+            };
+
+            let local = hour.with_timezone(&timezone);
+
+            heatmap[local.weekday().num_days_from_monday() as usize][local.hour() as usize] +=
+                connections;
+        }
+    }
+
+    if let Some(ckey) = ckey {
+        if heatmap.iter().flatten().all(|&count| count == 0)
+            && !player_exists(ckey, &mut connection).await
+        {
+            connection.close().await?;
+            return Err(Error::PlayerNotFound);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(heatmap)
+}
+
+pub async fn get_rounds_played(ckey: &Ckey, pool: &MySqlPool) -> Result<i64, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let query =
+        sqlx::query_scalar("SELECT COUNT(DISTINCT round_id) FROM connection_log WHERE ckey = ?")
+            .bind(ckey.as_str());
 
-    let achievements = query.fetch_all(&mut *connection).await?;
+    let rounds = query.fetch_one(&mut *connection).await?;
 
     connection.close().await?;
 
-    Ok(achievements)
+    Ok(rounds)
 }
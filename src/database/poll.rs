@@ -0,0 +1,608 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::{futures::StreamExt as _, FromFormField};
+use serde::Serialize;
+use sqlx::{mysql::MySqlRow, pool::PoolConnection, Executor as _, MySql, MySqlPool, Row as _};
+
+use super::error::Error;
+
+const POLL_SELECT: &str = "SELECT id, polltype, question, subtitle, created_datetime, starttime, endtime, createdby_ckey, adminonly, dontshow, multiplechoiceoptions, minimumplaytime AS minimum_playtime, CASE WHEN NOW() < starttime THEN 'upcoming' WHEN endtime IS NULL OR NOW() < endtime THEN 'active' ELSE 'ended' END AS status FROM poll_question WHERE deleted = 0";
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum PollType {
+    Option,
+    Text,
+    #[field(value = "rating")]
+    #[field(value = "numval")]
+    Rating,
+    #[field(value = "multichoice")]
+    MultiChoice,
+    Irv,
+}
+
+impl PollType {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            PollType::Option => "OPTION",
+            PollType::Text => "TEXT",
+            PollType::Rating => "NUMVAL",
+            PollType::MultiChoice => "MULTICHOICE",
+            PollType::Irv => "IRV",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum PollStatus {
+    Upcoming,
+    Active,
+    Ended,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Poll {
+    pub id: u32,
+    pub polltype: String,
+    pub question: String,
+    pub subtitle: Option<String>,
+    #[serde(with = "crate::serde::datetime")]
+    pub created_datetime: NaiveDateTime,
+    #[serde(with = "crate::serde::datetime")]
+    pub starttime: NaiveDateTime,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub endtime: Option<NaiveDateTime>,
+    pub createdby_ckey: Option<String>,
+    pub adminonly: bool,
+    pub dontshow: bool,
+    pub multiplechoiceoptions: Option<i32>,
+    pub minimum_playtime: i32,
+    pub status: String,
+}
+
+impl Poll {
+    fn from_row(row: &MySqlRow) -> Result<Self, Error> {
+        Ok(Poll {
+            id: row.try_get("id")?,
+            polltype: row.try_get("polltype")?,
+            question: row.try_get("question")?,
+            subtitle: row.try_get("subtitle")?,
+            created_datetime: row.try_get("created_datetime")?,
+            starttime: row.try_get("starttime")?,
+            endtime: row.try_get("endtime")?,
+            createdby_ckey: row.try_get("createdby_ckey")?,
+            adminonly: row.try_get("adminonly")?,
+            dontshow: row.try_get("dontshow")?,
+            multiplechoiceoptions: row.try_get("multiplechoiceoptions")?,
+            minimum_playtime: row.try_get("minimum_playtime")?,
+            status: row.try_get("status")?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PollFilter {
+    pub status: Option<PollStatus>,
+    pub polltype: Option<PollType>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub include_hidden: bool,
+}
+
+pub async fn get_polls(
+    filter: &PollFilter,
+    after: Option<u32>,
+    limit: u32,
+    pool: &MySqlPool,
+) -> Result<Vec<Poll>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = POLL_SELECT.to_string();
+
+    if !filter.include_hidden {
+        sql.push_str(" AND adminonly = 0 AND dontshow = 0");
+    }
+
+    match filter.status {
+        Some(PollStatus::Upcoming) => sql.push_str(" AND NOW() < starttime"),
+        Some(PollStatus::Active) => {
+            sql.push_str(" AND NOW() >= starttime AND (endtime IS NULL OR NOW() < endtime)")
+        }
+        Some(PollStatus::Ended) => sql.push_str(" AND endtime <= NOW()"),
+        None => {}
+    }
+
+    if filter.polltype.is_some() {
+        sql.push_str(" AND polltype = ?");
+    }
+
+    if filter.from.is_some() {
+        sql.push_str(" AND starttime >= ?");
+    }
+
+    if filter.to.is_some() {
+        sql.push_str(" AND starttime < DATE_ADD(?, INTERVAL 1 DAY)");
+    }
+
+    if after.is_some() {
+        sql.push_str(" AND id < ?");
+    }
+
+    sql.push_str(" ORDER BY id DESC LIMIT ?");
+
+    let mut query = sqlx::query(&sql);
+
+    if let Some(polltype) = filter.polltype {
+        query = query.bind(polltype.as_sql());
+    }
+
+    if let Some(from) = filter.from {
+        query = query.bind(from);
+    }
+
+    if let Some(to) = filter.to {
+        query = query.bind(to);
+    }
+
+    if let Some(after) = after {
+        query = query.bind(after);
+    }
+
+    query = query.bind(limit);
+
+    let mut polls = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            polls.push(Poll::from_row(&row?)?);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(polls)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PollOption {
+    pub id: u32,
+    pub text: String,
+    pub minval: Option<i32>,
+    pub maxval: Option<i32>,
+    pub descmin: Option<String>,
+    pub descmid: Option<String>,
+    pub descmax: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OptionTally {
+    pub id: u32,
+    pub text: String,
+    pub votes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RatingTally {
+    #[serde(flatten)]
+    pub option: PollOption,
+    pub votes: i64,
+    pub average: Option<f64>,
+    pub distribution: BTreeMap<i32, i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IrvRound {
+    pub tallies: BTreeMap<u32, usize>,
+    pub exhausted: usize,
+    pub eliminated: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextReply {
+    #[serde(with = "crate::serde::datetime")]
+    pub datetime: NaiveDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ckey: Option<String>,
+    pub replytext: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PollResults {
+    Option {
+        options: Vec<OptionTally>,
+        voters: i64,
+    },
+    MultiChoice {
+        options: Vec<OptionTally>,
+        voters: i64,
+    },
+    Rating {
+        options: Vec<RatingTally>,
+        voters: i64,
+    },
+    Irv {
+        options: Vec<OptionTally>,
+        ballots: usize,
+        rounds: Vec<IrvRound>,
+        winner: Option<u32>,
+    },
+    Text {
+        replies: Vec<TextReply>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct PollDetails {
+    #[serde(flatten)]
+    pub poll: Poll,
+    pub results: PollResults,
+}
+
+async fn get_options(
+    poll_id: u32,
+    connection: &mut PoolConnection<MySql>,
+) -> Result<Vec<PollOption>, Error> {
+    let query = sqlx::query(
+        "SELECT id, text, minval, maxval, descmin, descmid, descmax FROM poll_option WHERE pollid = ? AND deleted = 0 ORDER BY id ASC",
+    )
+    .bind(poll_id);
+
+    let mut options = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            options.push(PollOption {
+                id: row.try_get("id")?,
+                text: row.try_get("text")?,
+                minval: row.try_get("minval")?,
+                maxval: row.try_get("maxval")?,
+                descmin: row.try_get("descmin")?,
+                descmid: row.try_get("descmid")?,
+                descmax: row.try_get("descmax")?,
+            });
+        }
+    }
+
+    Ok(options)
+}
+
+// (optionid, ckey, rating) for every live vote, in the order they were cast
+async fn get_votes(
+    poll_id: u32,
+    connection: &mut PoolConnection<MySql>,
+) -> Result<Vec<(u32, String, Option<i32>)>, Error> {
+    let query = sqlx::query(
+        "SELECT optionid, ckey, rating FROM poll_vote WHERE pollid = ? AND deleted = 0 ORDER BY id ASC",
+    )
+    .bind(poll_id);
+
+    let mut votes = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            votes.push((
+                row.try_get("optionid")?,
+                row.try_get("ckey")?,
+                row.try_get("rating")?,
+            ));
+        }
+    }
+
+    Ok(votes)
+}
+
+fn tally_options(options: &[PollOption], votes: &[(u32, String, Option<i32>)]) -> Vec<OptionTally> {
+    options
+        .iter()
+        .map(|option| OptionTally {
+            id: option.id,
+            text: option.text.clone(),
+            votes: votes.iter().filter(|(id, _, _)| *id == option.id).count() as i64,
+        })
+        .collect()
+}
+
+fn count_voters(votes: &[(u32, String, Option<i32>)]) -> i64 {
+    votes
+        .iter()
+        .map(|(_, ckey, _)| ckey)
+        .collect::<HashSet<_>>()
+        .len() as i64
+}
+
+// each ballot lists option ids in order of preference
+fn run_irv(options: &[PollOption], ballots: &[Vec<u32>]) -> (Vec<IrvRound>, Option<u32>) {
+    let mut remaining = options
+        .iter()
+        .map(|option| option.id)
+        .collect::<HashSet<_>>();
+    let mut rounds = Vec::new();
+
+    while !remaining.is_empty() {
+        let mut tallies = remaining
+            .iter()
+            .map(|id| (*id, 0))
+            .collect::<BTreeMap<_, _>>();
+        let mut exhausted = 0;
+
+        for ballot in ballots {
+            match ballot.iter().find(|id| remaining.contains(id)) {
+                Some(id) => *tallies.entry(*id).or_default() += 1,
+                None => exhausted += 1,
+            }
+        }
+
+        let active = ballots.len() - exhausted;
+        let (leader, leader_votes) = tallies
+            .iter()
+            .max_by_key(|(id, votes)| (**votes, std::cmp::Reverse(**id)))
+            .map(|(id, votes)| (*id, *votes))
+            .unwrap_or_default();
+
+        if active == 0 {
+            rounds.push(IrvRound {
+                tallies,
+                exhausted,
+                eliminated: Vec::new(),
+            });
+            return (rounds, None);
+        }
+
+        if leader_votes * 2 > active || remaining.len() == 1 {
+            rounds.push(IrvRound {
+                tallies,
+                exhausted,
+                eliminated: Vec::new(),
+            });
+            return (rounds, Some(leader));
+        }
+
+        let fewest = tallies.values().min().copied().unwrap_or_default();
+        let eliminated = tallies
+            .iter()
+            .filter(|(_, votes)| **votes == fewest)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        // everyone left is tied, so there is no winner
+        if eliminated.len() == remaining.len() {
+            rounds.push(IrvRound {
+                tallies,
+                exhausted,
+                eliminated: Vec::new(),
+            });
+            return (rounds, None);
+        }
+
+        for id in &eliminated {
+            remaining.remove(id);
+        }
+
+        rounds.push(IrvRound {
+            tallies,
+            exhausted,
+            eliminated,
+        });
+    }
+
+    (rounds, None)
+}
+
+pub async fn get_poll(
+    poll_id: u32,
+    include_hidden: bool,
+    include_voters: bool,
+    pool: &MySqlPool,
+) -> Result<Option<PollDetails>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = format!("{POLL_SELECT} AND id = ?");
+
+    // hidden polls are filtered before any tallying, and look the same as missing ones
+    if !include_hidden {
+        sql.push_str(" AND adminonly = 0 AND dontshow = 0");
+    }
+
+    let query = sqlx::query(&sql).bind(poll_id);
+
+    let Some(row) = connection.fetch_optional(query).await? else {
+        connection.close().await?;
+        return Ok(None);
+    };
+
+    let poll = Poll::from_row(&row)?;
+
+    let results = match poll.polltype.as_str() {
+        "TEXT" => {
+            let query = sqlx::query(
+                "SELECT datetime, ckey, replytext FROM poll_textreply WHERE pollid = ? AND deleted = 0 ORDER BY id ASC",
+            )
+            .bind(poll_id);
+
+            let mut replies = Vec::new();
+
+            {
+                let mut rows = connection.fetch(query);
+
+                while let Some(row) = rows.next().await {
+                    let row = row?;
+
+                    replies.push(TextReply {
+                        datetime: row.try_get("datetime")?,
+                        ckey: match include_voters {
+                            true => Some(row.try_get("ckey")?),
+                            false => None,
+                        },
+                        replytext: row.try_get("replytext")?,
+                    });
+                }
+            }
+
+            PollResults::Text { replies }
+        }
+        polltype => {
+            let options = get_options(poll_id, &mut connection).await?;
+            let votes = get_votes(poll_id, &mut connection).await?;
+
+            match polltype {
+                "NUMVAL" => PollResults::Rating {
+                    voters: count_voters(&votes),
+                    options: options
+                        .into_iter()
+                        .map(|option| {
+                            let ratings = votes
+                                .iter()
+                                .filter(|(id, _, _)| *id == option.id)
+                                .filter_map(|(_, _, rating)| *rating)
+                                .collect::<Vec<_>>();
+
+                            let mut distribution = BTreeMap::new();
+                            for rating in &ratings {
+                                *distribution.entry(*rating).or_default() += 1;
+                            }
+
+                            RatingTally {
+                                option,
+                                votes: ratings.len() as i64,
+                                average: match ratings.is_empty() {
+                                    true => None,
+                                    false => Some(
+                                        ratings.iter().map(|r| *r as f64).sum::<f64>()
+                                            / ratings.len() as f64,
+                                    ),
+                                },
+                                distribution,
+                            }
+                        })
+                        .collect(),
+                },
+                "IRV" => {
+                    let mut ballots: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+                    for (id, ckey, _) in &votes {
+                        ballots.entry(ckey).or_default().push(*id);
+                    }
+
+                    let ballots = ballots.into_values().collect::<Vec<_>>();
+                    let (rounds, winner) = run_irv(&options, &ballots);
+
+                    // options are tallied by first preference
+                    let first_round = rounds.first().map(|round| &round.tallies);
+
+                    PollResults::Irv {
+                        options: options
+                            .iter()
+                            .map(|option| OptionTally {
+                                id: option.id,
+                                text: option.text.clone(),
+                                votes: first_round
+                                    .and_then(|tallies| tallies.get(&option.id))
+                                    .map_or(0, |votes| *votes as i64),
+                            })
+                            .collect(),
+                        ballots: ballots.len(),
+                        rounds,
+                        winner,
+                    }
+                }
+                "MULTICHOICE" => PollResults::MultiChoice {
+                    options: tally_options(&options, &votes),
+                    voters: count_voters(&votes),
+                },
+                _ => PollResults::Option {
+                    options: tally_options(&options, &votes),
+                    voters: count_voters(&votes),
+                },
+            }
+        }
+    };
+
+    connection.close().await?;
+
+    Ok(Some(PollDetails { poll, results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(ids: &[u32]) -> Vec<PollOption> {
+        ids.iter()
+            .map(|id| PollOption {
+                id: *id,
+                text: format!("Option {id}"),
+                minval: None,
+                maxval: None,
+                descmin: None,
+                descmid: None,
+                descmax: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn majority_wins_first_round() {
+        let ballots = [vec![1, 2], vec![1], vec![2, 1]];
+        let (rounds, winner) = run_irv(&options(&[1, 2]), &ballots);
+
+        assert_eq!(winner, Some(1));
+        assert_eq!(rounds.len(), 1);
+        assert_eq!(rounds[0].tallies, BTreeMap::from([(1, 2), (2, 1)]));
+    }
+
+    #[test]
+    fn eliminated_votes_transfer() {
+        let ballots = [vec![1], vec![1], vec![2], vec![2], vec![3, 2]];
+        let (rounds, winner) = run_irv(&options(&[1, 2, 3]), &ballots);
+
+        assert_eq!(winner, Some(2));
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].eliminated, [3]);
+        assert_eq!(rounds[1].tallies, BTreeMap::from([(1, 2), (2, 3)]));
+    }
+
+    #[test]
+    fn ties_for_last_are_eliminated_together() {
+        let ballots = [
+            vec![1],
+            vec![1],
+            vec![1],
+            vec![2, 1],
+            vec![3, 4],
+            vec![4, 3],
+        ];
+        let (rounds, winner) = run_irv(&options(&[1, 2, 3, 4]), &ballots);
+
+        assert_eq!(winner, Some(1));
+        assert_eq!(rounds[0].eliminated, [2, 3, 4]);
+        assert_eq!(rounds[1].exhausted, 2);
+    }
+
+    #[test]
+    fn full_tie_has_no_winner() {
+        let ballots = [vec![1], vec![2]];
+        let (rounds, winner) = run_irv(&options(&[1, 2]), &ballots);
+
+        assert_eq!(winner, None);
+        assert_eq!(rounds.len(), 1);
+        assert!(rounds[0].eliminated.is_empty());
+    }
+
+    #[test]
+    fn no_ballots_has_no_winner() {
+        let (rounds, winner) = run_irv(&options(&[1, 2]), &[]);
+
+        assert_eq!(winner, None);
+        assert_eq!(rounds.len(), 1);
+    }
+}
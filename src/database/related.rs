@@ -0,0 +1,192 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::NaiveDateTime;
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
+use sqlx::{pool::PoolConnection, Executor as _, MySql, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::{error::Error, player_exists};
+
+// caps how many first level relations get expanded when looking two hops out
+const MAX_EXPANDED: usize = 25;
+
+// the overlap is where both ckeys' first to last use of the same ip or cid intersect
+const SHARED_CONNECTIONS: &str = "SELECT c.ckey, 'ip' AS kind, INET_NTOA(c.ip) AS value, COUNT(*) AS evidence, GREATEST(MIN(c.datetime), own.first_seen) AS first_overlap, LEAST(MAX(c.datetime), own.last_seen) AS last_overlap FROM connection_log c JOIN (SELECT ip, MIN(datetime) AS first_seen, MAX(datetime) AS last_seen FROM connection_log WHERE ckey = ? AND datetime >= NOW() - INTERVAL ? DAY GROUP BY ip) AS own ON own.ip = c.ip WHERE c.ckey != ? AND c.datetime >= NOW() - INTERVAL ? DAY GROUP BY c.ckey, c.ip, own.first_seen, own.last_seen UNION ALL SELECT c.ckey, 'cid' AS kind, c.computerid AS value, COUNT(*) AS evidence, GREATEST(MIN(c.datetime), own.first_seen) AS first_overlap, LEAST(MAX(c.datetime), own.last_seen) AS last_overlap FROM connection_log c JOIN (SELECT computerid, MIN(datetime) AS first_seen, MAX(datetime) AS last_seen FROM connection_log WHERE ckey = ? AND datetime >= NOW() - INTERVAL ? DAY GROUP BY computerid) AS own ON own.computerid = c.computerid WHERE c.ckey != ? AND c.datetime >= NOW() - INTERVAL ? DAY GROUP BY c.ckey, c.computerid, own.first_seen, own.last_seen";
+
+#[derive(Debug, Serialize)]
+pub struct RelatedPlayer {
+    pub ckey: String,
+    pub depth: u8,
+    pub via: Option<String>,
+    pub known_alt: bool,
+    pub evidence: i64,
+    pub shared_ip_count: usize,
+    pub shared_computerid_count: usize,
+    pub shared_ips: BTreeSet<String>,
+    pub shared_computerids: BTreeSet<String>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub first_overlap: Option<NaiveDateTime>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub last_overlap: Option<NaiveDateTime>,
+}
+
+impl RelatedPlayer {
+    fn new(ckey: String, depth: u8, via: Option<String>) -> Self {
+        RelatedPlayer {
+            ckey,
+            depth,
+            via,
+            known_alt: false,
+            evidence: 0,
+            shared_ip_count: 0,
+            shared_computerid_count: 0,
+            shared_ips: BTreeSet::new(),
+            shared_computerids: BTreeSet::new(),
+            first_overlap: None,
+            last_overlap: None,
+        }
+    }
+}
+
+async fn find_related(
+    ckey: &str,
+    days: u32,
+    depth: u8,
+    via: Option<&str>,
+    connection: &mut PoolConnection<MySql>,
+) -> Result<HashMap<String, RelatedPlayer>, Error> {
+    let mut query = sqlx::query(SHARED_CONNECTIONS);
+
+    for _ in 0..2 {
+        query = query.bind(ckey).bind(days).bind(ckey).bind(days);
+    }
+
+    let mut related: HashMap<String, RelatedPlayer> = HashMap::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let other: String = row.try_get("ckey")?;
+            let kind: &str = row.try_get("kind")?;
+            let value: Option<String> = row.try_get("value")?;
+            let evidence: i64 = row.try_get("evidence")?;
+            let first_overlap: NaiveDateTime = row.try_get("first_overlap")?;
+            let last_overlap: NaiveDateTime = row.try_get("last_overlap")?;
+
+            let player = related
+                .entry(other.clone())
+                .or_insert_with(|| RelatedPlayer::new(other, depth, via.map(str::to_string)));
+
+            player.evidence += evidence;
+
+            let shared = match kind {
+                "ip" => {
+                    player.shared_ip_count += 1;
+                    &mut player.shared_ips
+                }
+                _ => {
+                    player.shared_computerid_count += 1;
+                    &mut player.shared_computerids
+                }
+            };
+
+            if let Some(value) = value {
+                shared.insert(value);
+            }
+
+            // both used it, but never during the same stretch of time
+            if first_overlap > last_overlap {
+                continue;
+            }
+
+            player.first_overlap = Some(
+                player
+                    .first_overlap
+                    .map_or(first_overlap, |first| first.min(first_overlap)),
+            );
+            player.last_overlap = Some(
+                player
+                    .last_overlap
+                    .map_or(last_overlap, |last| last.max(last_overlap)),
+            );
+        }
+    }
+
+    let query = sqlx::query(
+        "SELECT IF(ckey1 = ?, ckey2, ckey1) AS alt FROM known_alts WHERE ckey1 = ? OR ckey2 = ?",
+    )
+    .bind(ckey)
+    .bind(ckey)
+    .bind(ckey);
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let alt: String = row?.try_get("alt")?;
+
+            related
+                .entry(alt.clone())
+                .or_insert_with(|| RelatedPlayer::new(alt, depth, via.map(str::to_string)))
+                .known_alt = true;
+        }
+    }
+
+    Ok(related)
+}
+
+pub async fn get_related(
+    ckey: &Ckey,
+    days: u32,
+    depth: u8,
+    pool: &MySqlPool,
+) -> Result<Vec<RelatedPlayer>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    if !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    let mut related = find_related(ckey.as_str(), days, 1, None, &mut connection).await?;
+
+    if depth > 1 {
+        let mut expand = related
+            .values()
+            .map(|player| (player.ckey.clone(), player.evidence, player.known_alt))
+            .collect::<Vec<_>>();
+
+        // known alts first, then strongest evidence
+        expand.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)));
+        expand.truncate(MAX_EXPANDED);
+
+        for (via, _, _) in expand {
+            let second = find_related(&via, days, 2, Some(&via), &mut connection).await?;
+
+            for (other, player) in second {
+                if other != ckey.as_str() && !related.contains_key(&other) {
+                    related.insert(other, player);
+                }
+            }
+        }
+    }
+
+    connection.close().await?;
+
+    let mut related = related.into_values().collect::<Vec<_>>();
+
+    related.sort_by(|a, b| {
+        a.depth
+            .cmp(&b.depth)
+            .then(b.known_alt.cmp(&a.known_alt))
+            .then(b.evidence.cmp(&a.evidence))
+            .then(a.ckey.cmp(&b.ckey))
+    });
+
+    Ok(related)
+}
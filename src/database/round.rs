@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{
+    mysql::{MySqlArguments, MySqlRow},
+    query::Query,
+    Executor as _, MySql, MySqlPool, Row as _,
+};
+
+use crate::config::Config;
+
+use super::{
+    error::Error, get_round_id, parse_players, parse_threat, Citation, Death, DEATH_COLUMNS,
+};
+
+const ROUND_SELECT: &str = "SELECT id, initialize_datetime, start_datetime, shutdown_datetime, end_datetime, server_port, commit_hash, game_mode, game_mode_result, end_state, shuttle_name, map_name, station_name FROM round";
+
+#[derive(Debug, Serialize)]
+pub struct Round {
+    pub id: u32,
+    #[serde(with = "crate::serde::datetime")]
+    pub initialize_datetime: NaiveDateTime,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub start_datetime: Option<NaiveDateTime>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub shutdown_datetime: Option<NaiveDateTime>,
+    #[serde(with = "crate::serde::opt_datetime")]
+    pub end_datetime: Option<NaiveDateTime>,
+    pub server: Option<String>,
+    pub server_port: u16,
+    pub commit_hash: Option<String>,
+    pub game_mode: Option<String>,
+    pub game_mode_result: Option<String>,
+    pub end_state: Option<String>,
+    pub shuttle_name: Option<String>,
+    pub map_name: Option<String>,
+    pub station_name: Option<String>,
+    pub duration: Option<i64>,
+}
+
+impl Round {
+    pub(super) fn from_row(row: &MySqlRow, config: &Config) -> Result<Self, Error> {
+        let start_datetime: Option<NaiveDateTime> = row.try_get("start_datetime")?;
+        let end_datetime: Option<NaiveDateTime> = row.try_get("end_datetime")?;
+        let server_port: u16 = row.try_get("server_port")?;
+        // round.id is a signed column, unlike the round_id references to it
+        let id: i32 = row.try_get("id")?;
+
+        Ok(Round {
+            id: id as u32,
+            initialize_datetime: row.try_get("initialize_datetime")?,
+            start_datetime,
+            shutdown_datetime: row.try_get("shutdown_datetime")?,
+            end_datetime,
+            server: config.server_name(server_port).map(str::to_string),
+            server_port,
+            commit_hash: row.try_get("commit_hash")?,
+            game_mode: row.try_get("game_mode")?,
+            game_mode_result: row.try_get("game_mode_result")?,
+            end_state: row.try_get("end_state")?,
+            shuttle_name: row.try_get("shuttle_name")?,
+            map_name: row.try_get("map_name")?,
+            station_name: row.try_get("station_name")?,
+            duration: match (start_datetime, end_datetime) {
+                (Some(start), Some(end)) => {
+                    Some(end.signed_duration_since(start).num_seconds() / 60)
+                }
+                _ => None,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoundDetails {
+    #[serde(flatten)]
+    pub round: Round,
+    pub players: Option<u64>,
+    pub threat_level: Option<i32>,
+    pub readied_players: Option<i32>,
+    pub test_merges: Vec<u32>,
+    pub deaths: Vec<Death>,
+    pub citations: Vec<Citation>,
+}
+
+pub async fn get_round(
+    round_id: u32,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<Option<RoundDetails>, Error> {
+    // the live round is still being written to
+    if let Some(current) = get_round_id(config).await? {
+        if round_id as i64 >= current as i64 {
+            return Ok(None);
+        }
+    }
+
+    let mut connection = pool.acquire().await?;
+
+    let sql = format!("{ROUND_SELECT} WHERE id = ?");
+    let query = sqlx::query(&sql).bind(round_id);
+
+    let Some(row) = connection.fetch_optional(query).await? else {
+        connection.close().await?;
+        return Ok(None);
+    };
+
+    let round = Round::from_row(&row, config)?;
+
+    let mut details = RoundDetails {
+        round,
+        players: None,
+        threat_level: None,
+        readied_players: None,
+        test_merges: Vec::new(),
+        deaths: Vec::new(),
+        citations: Vec::new(),
+    };
+
+    let query = sqlx::query(
+        "SELECT key_name, json FROM feedback WHERE round_id = ? AND key_name IN ('round_end_stats', 'dynamic_threat', 'testmerged_prs')",
+    )
+    .bind(round_id);
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let key_name: String = row.try_get("key_name")?;
+            let json: Value = row.try_get("json")?;
+
+            match key_name.as_str() {
+                "round_end_stats" => details.players = parse_players(&json),
+                "dynamic_threat" => {
+                    (details.threat_level, details.readied_players) = parse_threat(&json);
+                }
+                "testmerged_prs" => {
+                    if let Some(data) = json["data"].as_object() {
+                        details.test_merges = data
+                            .values()
+                            .filter_map(|pr| pr["number"].as_str()?.parse().ok())
+                            .collect();
+                        details.test_merges.sort_unstable();
+                        details.test_merges.dedup();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let sql = format!("SELECT {DEATH_COLUMNS} FROM death WHERE round_id = ? ORDER BY tod ASC");
+    let query = sqlx::query(&sql).bind(round_id);
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            details.deaths.push(Death::from_row(&row?)?);
+        }
+    }
+
+    let query = sqlx::query(
+        "SELECT round_id, sender_ic, recipient, crime, fine, timestamp FROM citation WHERE round_id = ? ORDER BY timestamp ASC",
+    )
+    .bind(round_id);
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            details.citations.push(Citation::from_row(&row?)?);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(Some(details))
+}
+
+#[derive(Debug, Default)]
+pub struct RoundFilter<'a> {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub map: Option<&'a str>,
+    pub server_port: Option<u16>,
+    pub min_duration: Option<u32>,
+    pub end_state: Option<&'a str>,
+}
+
+impl RoundFilter<'_> {
+    // every condition is prefixed with AND, so this goes after a WHERE on the live round
+    fn push_sql(&self, sql: &mut String) {
+        if self.from.is_some() {
+            sql.push_str(" AND round.start_datetime >= ?");
+        }
+
+        if self.to.is_some() {
+            sql.push_str(" AND round.start_datetime < DATE_ADD(?, INTERVAL 1 DAY)");
+        }
+
+        if self.map.is_some() {
+            sql.push_str(" AND round.map_name = ?");
+        }
+
+        if self.server_port.is_some() {
+            sql.push_str(" AND round.server_port = ?");
+        }
+
+        if self.min_duration.is_some() {
+            sql.push_str(
+                " AND TIMESTAMPDIFF(MINUTE, round.start_datetime, round.end_datetime) >= ?",
+            );
+        }
+
+        if self.end_state.is_some() {
+            sql.push_str(" AND round.end_state = ?");
+        }
+    }
+
+    fn bind<'q>(
+        &self,
+        mut query: Query<'q, MySql, MySqlArguments>,
+    ) -> Query<'q, MySql, MySqlArguments>
+    where
+        Self: 'q,
+    {
+        if let Some(from) = self.from {
+            query = query.bind(from);
+        }
+
+        if let Some(to) = self.to {
+            query = query.bind(to);
+        }
+
+        if let Some(map) = self.map {
+            query = query.bind(map);
+        }
+
+        if let Some(server_port) = self.server_port {
+            query = query.bind(server_port);
+        }
+
+        if let Some(min_duration) = self.min_duration {
+            query = query.bind(min_duration);
+        }
+
+        if let Some(end_state) = self.end_state {
+            query = query.bind(end_state);
+        }
+
+        query
+    }
+}
+
+// the live round and anything after it are left out, with no live round nothing is
+pub(super) async fn round_bound(config: &Config) -> Result<i64, Error> {
+    Ok(get_round_id(config).await?.map_or(i64::MAX, i64::from))
+}
+
+pub async fn get_rounds(
+    filter: &RoundFilter<'_>,
+    after: Option<u32>,
+    limit: u32,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<Vec<Round>, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = format!("{ROUND_SELECT} WHERE round.id < ?");
+
+    filter.push_sql(&mut sql);
+
+    if after.is_some() {
+        sql.push_str(" AND round.id < ?");
+    }
+
+    sql.push_str(" ORDER BY round.id DESC LIMIT ?");
+
+    let mut query = filter.bind(sqlx::query(&sql).bind(bound));
+
+    if let Some(after) = after {
+        query = query.bind(after);
+    }
+
+    query = query.bind(limit);
+
+    let mut rounds = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            rounds.push(Round::from_row(&row?, config)?);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(rounds)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoundStats {
+    pub key: Option<String>,
+    pub rounds: i64,
+    pub average_duration: Option<f64>,
+    pub average_players: Option<f64>,
+    pub average_threat: Option<f64>,
+    pub shuttle_call_ratio: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoundStatsSummary {
+    pub maps: Vec<RoundStats>,
+    pub months: Vec<RoundStats>,
+}
+
+#[derive(Debug, Default)]
+struct StatsTotals {
+    rounds: i64,
+    duration_sum: i64,
+    duration_count: i64,
+    players_sum: u64,
+    players_count: i64,
+    threat_sum: i64,
+    threat_count: i64,
+    shuttle_calls: i64,
+}
+
+impl StatsTotals {
+    fn add(
+        &mut self,
+        duration: Option<i64>,
+        players: Option<u64>,
+        threat: Option<i32>,
+        shuttle_called: bool,
+    ) {
+        self.rounds += 1;
+
+        if let Some(duration) = duration {
+            self.duration_sum += duration;
+            self.duration_count += 1;
+        }
+
+        if let Some(players) = players {
+            self.players_sum += players;
+            self.players_count += 1;
+        }
+
+        if let Some(threat) = threat {
+            self.threat_sum += i64::from(threat);
+            self.threat_count += 1;
+        }
+
+        if shuttle_called {
+            self.shuttle_calls += 1;
+        }
+    }
+
+    fn into_stats(self, key: Option<String>) -> RoundStats {
+        let average = |sum: f64, count: i64| match count {
+            0 => None,
+            count => Some(sum / count as f64),
+        };
+
+        RoundStats {
+            key,
+            rounds: self.rounds,
+            average_duration: average(self.duration_sum as f64, self.duration_count),
+            average_players: average(self.players_sum as f64, self.players_count),
+            average_threat: average(self.threat_sum as f64, self.threat_count),
+            shuttle_call_ratio: average(self.shuttle_calls as f64, self.rounds).unwrap_or(0.0),
+        }
+    }
+}
+
+// one row per round with the same feedback the overview reads, shuttle_reason is only recorded
+// when the emergency shuttle is called
+const ROUND_STATS_SELECT: &str = "SELECT round.map_name, DATE_FORMAT(round.start_datetime, '%Y-%m') AS month, TIMESTAMPDIFF(MINUTE, round.start_datetime, round.end_datetime) AS duration, players.json AS players, threat.json AS threat, CAST(shuttle.round_id IS NOT NULL AS SIGNED) AS shuttle_called FROM round LEFT JOIN feedback players ON players.round_id = round.id AND players.key_name = 'round_end_stats' LEFT JOIN feedback threat ON threat.round_id = round.id AND threat.key_name = 'dynamic_threat' LEFT JOIN (SELECT DISTINCT round_id FROM feedback WHERE key_name = 'shuttle_reason') AS shuttle ON shuttle.round_id = round.id WHERE round.id < ?";
+
+pub async fn get_round_stats(
+    filter: &RoundFilter<'_>,
+    config: &Config,
+    pool: &MySqlPool,
+) -> Result<RoundStatsSummary, Error> {
+    let bound = round_bound(config).await?;
+
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = ROUND_STATS_SELECT.to_string();
+
+    filter.push_sql(&mut sql);
+
+    let query = filter.bind(sqlx::query(&sql).bind(bound));
+
+    let mut maps: BTreeMap<Option<String>, StatsTotals> = BTreeMap::new();
+    let mut months: BTreeMap<Option<String>, StatsTotals> = BTreeMap::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let duration: Option<i64> = row.try_get("duration")?;
+            let players: Option<Value> = row.try_get("players")?;
+            let threat: Option<Value> = row.try_get("threat")?;
+            let shuttle_called = row.try_get::<i64, _>("shuttle_called")? != 0;
+
+            let players = players.as_ref().and_then(parse_players);
+            let threat = threat.as_ref().and_then(|threat| parse_threat(threat).0);
+
+            for (totals, key) in [(&mut maps, "map_name"), (&mut months, "month")] {
+                totals.entry(row.try_get(key)?).or_default().add(
+                    duration,
+                    players,
+                    threat,
+                    shuttle_called,
+                );
+            }
+        }
+    }
+
+    connection.close().await?;
+
+    let into_stats = |totals: BTreeMap<Option<String>, StatsTotals>| {
+        totals
+            .into_iter()
+            .map(|(key, totals)| totals.into_stats(key))
+            .collect()
+    };
+
+    Ok(RoundStatsSummary {
+        maps: into_stats(maps),
+        months: into_stats(months),
+    })
+}
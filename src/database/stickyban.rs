@@ -0,0 +1,182 @@
+use chrono::NaiveDateTime;
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
+use sqlx::{mysql::MySqlRow, Executor as _, MySqlPool, Row as _};
+
+use crate::ckey::Ckey;
+
+use super::{error::Error, escape_like, player_exists};
+
+const STICKYBAN_SELECT: &str = "SELECT ckey, reason, banning_ckey, datetime, (SELECT COUNT(*) FROM stickyban_matched_ckey WHERE stickyban = stickyban.ckey) AS matched_ckeys, (SELECT COUNT(*) FROM stickyban_matched_ip WHERE stickyban = stickyban.ckey) AS matched_ips, (SELECT COUNT(*) FROM stickyban_matched_cid WHERE stickyban = stickyban.ckey) AS matched_cids FROM stickyban";
+
+#[derive(Debug, Serialize)]
+pub struct Stickyban {
+    pub ckey: String,
+    pub reason: String,
+    pub banning_ckey: String,
+    #[serde(with = "crate::serde::datetime")]
+    pub datetime: NaiveDateTime,
+    pub matched_ckeys: i64,
+    pub matched_ips: i64,
+    pub matched_cids: i64,
+}
+
+impl Stickyban {
+    fn from_row(row: &MySqlRow) -> Result<Self, Error> {
+        Ok(Stickyban {
+            ckey: row.try_get("ckey")?,
+            reason: row.try_get("reason")?,
+            banning_ckey: row.try_get("banning_ckey")?,
+            datetime: row.try_get("datetime")?,
+            matched_ckeys: row.try_get("matched_ckeys")?,
+            matched_ips: row.try_get("matched_ips")?,
+            matched_cids: row.try_get("matched_cids")?,
+        })
+    }
+}
+
+pub async fn get_stickybans(
+    search: Option<&str>,
+    pool: &MySqlPool,
+) -> Result<Vec<Stickyban>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let mut sql = STICKYBAN_SELECT.to_string();
+
+    if search.is_some() {
+        sql.push_str(" WHERE ckey LIKE ? OR reason LIKE ?");
+    }
+
+    sql.push_str(" ORDER BY datetime DESC");
+
+    let mut query = sqlx::query(&sql);
+
+    if let Some(search) = search {
+        let pattern = format!("%{}%", escape_like(search));
+        query = query.bind(pattern.clone()).bind(pattern);
+    }
+
+    let mut stickybans = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            stickybans.push(Stickyban::from_row(&row?)?);
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(stickybans)
+}
+
+#[derive(Debug, Serialize)]
+pub struct StickybanMatch {
+    pub ckey: String,
+    #[serde(with = "crate::serde::datetime")]
+    pub first_matched: NaiveDateTime,
+    #[serde(with = "crate::serde::datetime")]
+    pub last_matched: NaiveDateTime,
+    pub exempt: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StickybanDetails {
+    #[serde(flatten)]
+    pub stickyban: Stickyban,
+    pub matches: Vec<StickybanMatch>,
+}
+
+pub async fn get_stickyban(
+    ckey: &Ckey,
+    pool: &MySqlPool,
+) -> Result<Option<StickybanDetails>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let sql = format!("{STICKYBAN_SELECT} WHERE ckey = ?");
+    let query = sqlx::query(&sql).bind(ckey.as_str());
+
+    let Some(row) = connection.fetch_optional(query).await? else {
+        connection.close().await?;
+        return Ok(None);
+    };
+
+    let stickyban = Stickyban::from_row(&row)?;
+
+    let query = sqlx::query(
+        "SELECT matched_ckey, first_matched, last_matched, exempt FROM stickyban_matched_ckey WHERE stickyban = ? ORDER BY last_matched DESC",
+    )
+    .bind(ckey.as_str());
+
+    let mut matches = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            matches.push(StickybanMatch {
+                ckey: row.try_get("matched_ckey")?,
+                first_matched: row.try_get("first_matched")?,
+                last_matched: row.try_get("last_matched")?,
+                exempt: row.try_get("exempt")?,
+            });
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(Some(StickybanDetails { stickyban, matches }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchedStickyban {
+    #[serde(flatten)]
+    pub stickyban: Stickyban,
+    #[serde(with = "crate::serde::datetime")]
+    pub first_matched: NaiveDateTime,
+    #[serde(with = "crate::serde::datetime")]
+    pub last_matched: NaiveDateTime,
+    pub exempt: bool,
+}
+
+pub async fn get_matched_stickybans(
+    ckey: &Ckey,
+    pool: &MySqlPool,
+) -> Result<Vec<MatchedStickyban>, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let sql = format!(
+        "SELECT stickyban.*, matched.first_matched, matched.last_matched, matched.exempt FROM ({STICKYBAN_SELECT}) AS stickyban JOIN stickyban_matched_ckey matched ON matched.stickyban = stickyban.ckey WHERE matched.matched_ckey = ? ORDER BY matched.last_matched DESC"
+    );
+
+    let query = sqlx::query(&sql).bind(ckey.as_str());
+
+    let mut stickybans = Vec::new();
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            stickybans.push(MatchedStickyban {
+                stickyban: Stickyban::from_row(&row)?,
+                first_matched: row.try_get("first_matched")?,
+                last_matched: row.try_get("last_matched")?,
+                exempt: row.try_get("exempt")?,
+            });
+        }
+    }
+
+    if stickybans.is_empty() && !player_exists(ckey, &mut connection).await {
+        connection.close().await?;
+        return Err(Error::PlayerNotFound);
+    }
+
+    connection.close().await?;
+
+    Ok(stickybans)
+}
@@ -1,22 +1,29 @@
+use std::collections::HashMap;
+
 use rand::Rng as _;
 use regex::Regex;
+use rocket::futures::StreamExt as _;
+use serde::Serialize;
 use sqlx::{pool::PoolConnection, Executor as _, MySql, MySqlPool, Row as _};
 
-use crate::http::discord::{self, User};
+use crate::{
+    ckey::Ckey,
+    http::discord::{self, User},
+};
 
 use super::{error::Error, player_exists};
 
 pub async fn verify_discord(
     discord_id: &str,
     one_time_token: Option<&str>,
-    ckey: Option<&str>,
+    ckey: Option<&Ckey>,
     skip_ckey: Option<bool>,
     pool: &MySqlPool,
-) -> Result<Option<String>, Error> {
+) -> Result<Option<Ckey>, Error> {
     let mut connection = pool.acquire().await?;
 
     if let Ok(ckey) = ckey_by_discord_id(discord_id, &mut connection).await {
-        return Err(Error::DiscordInUse(ckey));
+        return Err(Error::DiscordInUse(ckey.to_string()));
     }
 
     if let Some(one_time_token) = one_time_token {
@@ -63,7 +70,7 @@ pub async fn verify_discord(
             "INSERT INTO discord_links (discord_id, ckey, one_time_token, valid) VALUES (?, ?, ?, 1)",
         )
         .bind(discord_id)
-        .bind(ckey.to_lowercase())
+        .bind(ckey.as_str())
         .bind(token);
 
         connection.execute(query).await?;
@@ -77,7 +84,7 @@ pub async fn verify_discord(
 
 pub async fn unverify_discord(
     discord_id: Option<&str>,
-    ckey: Option<&str>,
+    ckey: Option<&Ckey>,
     pool: &MySqlPool,
 ) -> Result<String, Error> {
     let mut connection = pool.acquire().await?;
@@ -92,13 +99,12 @@ pub async fn unverify_discord(
         connection.execute(query).await?;
         connection.close().await?;
 
-        return Ok(ckey);
+        return Ok(ckey.to_string());
     } else if let Some(ckey) = ckey {
         let discord_id = discord_id_by_ckey(ckey, &mut connection).await?;
 
-        let query =
-            sqlx::query("UPDATE discord_links SET valid = 0 WHERE LOWER(ckey) = ? AND valid = 1")
-                .bind(ckey.to_lowercase());
+        let query = sqlx::query("UPDATE discord_links SET valid = 0 WHERE ckey = ? AND valid = 1")
+            .bind(ckey.as_str());
 
         connection.execute(query).await?;
         connection.close().await?;
@@ -112,24 +118,58 @@ pub async fn unverify_discord(
 pub async fn ckey_by_discord_id(
     discord_id: &str,
     connection: &mut PoolConnection<MySql>,
-) -> Result<String, Error> {
+) -> Result<Ckey, Error> {
     let query = sqlx::query("SELECT ckey FROM discord_links WHERE discord_id = ? AND valid = 1")
         .bind(discord_id);
 
     if let Ok(row) = connection.fetch_one(query).await {
-        return Ok(row.try_get("ckey")?);
+        return Ok(Ckey::new(row.try_get::<&str, _>("ckey")?)?);
     }
 
     Err(Error::NotLinked)
 }
 
+pub async fn ckeys_by_discord_ids(
+    discord_ids: &[String],
+    connection: &mut PoolConnection<MySql>,
+) -> Result<HashMap<String, String>, Error> {
+    let mut ckeys = HashMap::new();
+
+    if discord_ids.is_empty() {
+        return Ok(ckeys);
+    }
+
+    let placeholders = vec!["?"; discord_ids.len()].join(", ");
+
+    let sql = format!(
+        "SELECT CAST(discord_id AS CHAR) AS discord_id, ckey FROM discord_links WHERE valid = 1 AND discord_id IN ({placeholders})"
+    );
+
+    let mut query = sqlx::query(&sql);
+
+    for discord_id in discord_ids {
+        query = query.bind(discord_id);
+    }
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            ckeys.insert(row.try_get("discord_id")?, row.try_get("ckey")?);
+        }
+    }
+
+    Ok(ckeys)
+}
+
 pub async fn discord_id_by_ckey(
-    ckey: &str,
+    ckey: &Ckey,
     connection: &mut PoolConnection<MySql>,
 ) -> Result<i64, Error> {
-    let query =
-        sqlx::query("SELECT discord_id FROM discord_links WHERE LOWER(ckey) = ? AND valid = 1")
-            .bind(ckey.to_lowercase());
+    let query = sqlx::query("SELECT discord_id FROM discord_links WHERE ckey = ? AND valid = 1")
+        .bind(ckey.as_str());
 
     if let Ok(row) = connection.fetch_one(query).await {
         return Ok(row.try_get("discord_id")?);
@@ -163,7 +203,7 @@ async fn discord_id_by_token(
 }
 
 pub async fn fetch_discord_by_ckey(
-    ckey: &str,
+    ckey: &Ckey,
     discord_token: &str,
     pool: &MySqlPool,
 ) -> Result<User, Error> {
@@ -178,7 +218,7 @@ pub async fn fetch_discord_by_ckey(
     Ok(user)
 }
 
-pub async fn get_ckey_by_discord_id(discord_id: &str, pool: &MySqlPool) -> Result<String, Error> {
+pub async fn get_ckey_by_discord_id(discord_id: &str, pool: &MySqlPool) -> Result<Ckey, Error> {
     let mut connection = pool.acquire().await?;
 
     let ckey = ckey_by_discord_id(discord_id, &mut connection).await?;
@@ -188,6 +228,111 @@ pub async fn get_ckey_by_discord_id(discord_id: &str, pool: &MySqlPool) -> Resul
     Ok(ckey)
 }
 
+pub async fn get_discord_id_by_ckey(ckey: &Ckey, pool: &MySqlPool) -> Result<i64, Error> {
+    let mut connection = pool.acquire().await?;
+
+    let discord_id = discord_id_by_ckey(ckey, &mut connection).await?;
+
+    connection.close().await?;
+
+    Ok(discord_id)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", content = "value", rename_all = "snake_case")]
+pub enum DiscordLink {
+    Linked(String),
+    NotLinked,
+    NotFound,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BulkDiscordLinks {
+    pub ckeys: HashMap<String, DiscordLink>,
+    pub discord_ids: HashMap<u64, DiscordLink>,
+}
+
+pub async fn get_bulk_discord_links(
+    ckeys: &[Ckey],
+    discord_ids: &[u64],
+    pool: &MySqlPool,
+) -> Result<BulkDiscordLinks, Error> {
+    let mut links = BulkDiscordLinks::default();
+
+    for ckey in ckeys {
+        links.ckeys.insert(ckey.to_string(), DiscordLink::NotFound);
+    }
+
+    for discord_id in discord_ids {
+        links
+            .discord_ids
+            .insert(*discord_id, DiscordLink::NotLinked);
+    }
+
+    let mut selects = Vec::new();
+
+    if !ckeys.is_empty() {
+        let placeholders = vec!["?"; ckeys.len()].join(", ");
+        selects.push(format!(
+            "SELECT 'ckey' AS source, player.ckey, CAST(discord_links.discord_id AS CHAR) AS discord_id FROM player LEFT JOIN discord_links ON discord_links.ckey = player.ckey AND discord_links.valid = 1 WHERE player.ckey IN ({placeholders})"
+        ));
+    }
+
+    if !discord_ids.is_empty() {
+        let placeholders = vec!["?"; discord_ids.len()].join(", ");
+        selects.push(format!(
+            "SELECT 'discord_id' AS source, ckey, CAST(discord_id AS CHAR) AS discord_id FROM discord_links WHERE valid = 1 AND discord_id IN ({placeholders})"
+        ));
+    }
+
+    if selects.is_empty() {
+        return Ok(links);
+    }
+
+    let sql = selects.join(" UNION ALL ");
+
+    let mut query = sqlx::query(&sql);
+
+    for ckey in ckeys {
+        query = query.bind(ckey.as_str());
+    }
+
+    for discord_id in discord_ids {
+        query = query.bind(discord_id);
+    }
+
+    let mut connection = pool.acquire().await?;
+
+    {
+        let mut rows = connection.fetch(query);
+
+        while let Some(row) = rows.next().await {
+            let row = row?;
+
+            let source: String = row.try_get("source")?;
+            let ckey: String = row.try_get("ckey")?;
+            let discord_id: Option<String> = row.try_get("discord_id")?;
+
+            if source == "ckey" {
+                let link = match discord_id {
+                    Some(discord_id) => DiscordLink::Linked(discord_id),
+                    None => DiscordLink::NotLinked,
+                };
+
+                links.ckeys.insert(ckey, link);
+            } else if let Some(Ok(discord_id)) = discord_id.map(|id| id.parse()) {
+                links
+                    .discord_ids
+                    .insert(discord_id, DiscordLink::Linked(ckey));
+            }
+        }
+    }
+
+    connection.close().await?;
+
+    Ok(links)
+}
+
 async fn generate_one_time_token(connection: &mut PoolConnection<MySql>) -> String {
     loop {
         let token: u32 = rand::thread_rng().gen_range(1..=999_999);
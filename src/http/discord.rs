@@ -1,8 +1,11 @@
 use std::{collections::HashSet, sync::Arc};
 
+use chrono::DateTime;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use tokio::sync::Mutex;
+use urlencoding::encode;
 
 use super::{Error, REQWEST_CLIENT};
 
@@ -41,11 +44,80 @@ pub async fn get_user(id: i64, token: &str) -> Result<User, Error> {
     Ok(user)
 }
 
+#[derive(Debug, Deserialize)]
+struct OAuthErrorMessage {
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccessToken {
+    // https://discord.com/developers/docs/topics/oauth2#authorization-code-grant-access-token-response
+    pub access_token: String,
+}
+
+pub fn authorize_url(client_id: i64, redirect_uri: &str, state: &str) -> String {
+    format!(
+        "https://discord.com/oauth2/authorize?response_type=code&client_id={client_id}&scope=identify&state={}&redirect_uri={}",
+        encode(state),
+        encode(redirect_uri)
+    )
+}
+
+pub async fn exchange_code(
+    code: &str,
+    client_id: i64,
+    client_secret: &str,
+    redirect_uri: &str,
+) -> Result<AccessToken, Error> {
+    let client_id = client_id.to_string();
+
+    let form = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", &client_id),
+        ("client_secret", client_secret),
+    ];
+
+    let response = REQWEST_CLIENT
+        .post("https://discord.com/api/v10/oauth2/token")
+        .form(&form)
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    let Ok(token) = serde_json::from_str(&response) else {
+        let error: OAuthErrorMessage = serde_json::from_str(&response)?;
+        return Err(Error::OAuth(error.error));
+    };
+
+    Ok(token)
+}
+
+pub async fn get_current_user(access_token: &str) -> Result<User, Error> {
+    let response = REQWEST_CLIENT
+        .get("https://discord.com/api/v10/users/@me")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    let Ok(user) = serde_json::from_str(&response) else {
+        let error: ErrorMessage = serde_json::from_str(&response)?;
+        return Err(Error::Discord(error.code));
+    };
+
+    Ok(user)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GuildMember {
     // https://discord.com/developers/docs/resources/guild#guild-member-object
     pub roles: HashSet<String>,
     pub user: User,
+    pub joined_at: Option<String>,
 }
 
 pub async fn get_guild_member(
@@ -73,25 +145,15 @@ pub async fn get_guild_member(
     Ok(member)
 }
 
+const MEMBERS_SEARCH_LIMIT: usize = 1000;
+
 pub async fn search_members(
     guild_id: i64,
-    query: String,
+    mut query: Value,
     token: &str,
 ) -> Result<Vec<GuildMember>, Error> {
     let _lock = DISCORD_API_LOCK.lock().await;
 
-    let response = REQWEST_CLIENT
-        .post(format!(
-            "https://discord.com/api/v10/guilds/{guild_id}/members-search"
-        ))
-        .header("Authorization", format!("Bot {token}"))
-        .header("Content-Type", "application/json")
-        .body(query)
-        .send()
-        .await?
-        .text()
-        .await?;
-
     #[derive(Deserialize)]
     struct Response {
         pub members: Vec<ResponseMember>,
@@ -102,12 +164,54 @@ pub async fn search_members(
         pub member: GuildMember,
     }
 
-    let Ok(response) = serde_json::from_str::<Response>(&response) else {
-        let error: ErrorMessage = serde_json::from_str(&response)?;
-        return Err(Error::Discord(error.code));
-    };
-
-    let members = response.members.into_iter().map(|m| m.member).collect();
+    query["limit"] = json!(MEMBERS_SEARCH_LIMIT);
+    // oldest members first, so `after` walks forward through the guild
+    query["sort"] = json!(2);
+
+    let mut members = Vec::new();
+
+    loop {
+        let response = REQWEST_CLIENT
+            .post(format!(
+                "https://discord.com/api/v10/guilds/{guild_id}/members-search"
+            ))
+            .header("Authorization", format!("Bot {token}"))
+            .json(&query)
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        let Ok(response) = serde_json::from_str::<Response>(&response) else {
+            let error: ErrorMessage = serde_json::from_str(&response)?;
+            return Err(Error::Discord(error.code));
+        };
+
+        let page_size = response.members.len();
+
+        members.extend(response.members.into_iter().map(|m| m.member));
+
+        if page_size < MEMBERS_SEARCH_LIMIT {
+            break;
+        }
+
+        let Some(last) = members.last() else {
+            break;
+        };
+
+        let Some(joined_at) = last
+            .joined_at
+            .as_deref()
+            .and_then(|joined_at| DateTime::parse_from_rfc3339(joined_at).ok())
+        else {
+            break;
+        };
+
+        query["after"] = json!({
+            "guild_joined_at": joined_at.timestamp_millis(),
+            "user_id": last.user.id,
+        });
+    }
 
     Ok(members)
 }
@@ -7,4 +7,6 @@ pub enum Error {
     SerdeJson(#[from] serde_json::Error),
     #[error("discord api error")]
     Discord(u32),
+    #[error("discord oauth2 error: {0}")]
+    OAuth(String),
 }
@@ -1,10 +1,11 @@
 use rocket::{catch, catchers, http::Status, Config as RocketConfig, Request};
 use thiserror::Error;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{config::Config, cors::cors, database::Database};
 
 mod byond;
+mod ckey;
 mod config;
 mod cors;
 mod database;
@@ -20,6 +21,10 @@ async fn main() -> Result<(), Error> {
     let config = Config::read_from_file()?;
     let database = Database::new(&config.database)?;
 
+    if config.patreon.webhook_secret.is_empty() {
+        warn!("No Patreon webhook secret is configured, the webhook endpoint is disabled");
+    }
+
     info!(
         "Server has launched from http://{}:{}",
         config.address, config.port
@@ -30,6 +35,7 @@ async fn main() -> Result<(), Error> {
         port: config.port,
         cli_colors: config.cli_colors,
         log_level: config.log_level,
+        secret_key: config.secret_key.clone(),
         ..Default::default()
     };
 
@@ -37,11 +43,11 @@ async fn main() -> Result<(), Error> {
         .attach(cors()?)
         .manage(config)
         .manage(database)
-        .register("/", catchers![empty_catcher]);
+        .register("/", catchers![empty_catcher, unprocessable_catcher]);
 
     let rocket = routes::mount(rocket);
 
-    rocket.launch().await?;
+    rocket.launch().await.map_err(Box::new)?;
 
     Ok(())
 }
@@ -49,12 +55,25 @@ async fn main() -> Result<(), Error> {
 #[catch(default)]
 fn empty_catcher(_: Status, _: &Request) {}
 
+// rocket forwards unparsable query and path parameters as 422, json bodies that fail to
+// deserialize keep it since the request itself was well formed
+#[catch(422)]
+fn unprocessable_catcher(request: &Request) -> (Status, ()) {
+    match request
+        .content_type()
+        .is_some_and(|content_type| content_type.is_json())
+    {
+        true => (Status::UnprocessableEntity, ()),
+        false => (Status::BadRequest, ()),
+    }
+}
+
 #[derive(Debug, Error)]
 #[error(transparent)]
 enum Error {
     Config(#[from] config::Error),
     Cors(#[from] rocket_cors::Error),
-    Rocket(#[from] rocket::Error),
+    Rocket(#[from] Box<rocket::Error>),
     Sqlx(#[from] sqlx::Error),
     SetGlobalDefault(#[from] tracing::subscriber::SetGlobalDefaultError),
 }
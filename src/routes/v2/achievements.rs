@@ -0,0 +1,36 @@
+use rocket::{get, http::Status, State};
+
+use crate::{database::*, Database};
+
+use super::{common::ApiKey, Json};
+
+#[get("/achievements")]
+pub async fn index(
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<CatalogAchievement>>, Status> {
+    match get_achievement_catalog(&database.pool).await {
+        Ok(catalog) => Ok(Json::Ok(catalog)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/achievements/<key>?<limit>")]
+pub async fn achievement(
+    key: &str,
+    limit: Option<u32>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<AchievementDetails>, Status> {
+    let limit = limit.unwrap_or(25);
+
+    if !(1..=100).contains(&limit) {
+        return Err(Status::BadRequest);
+    }
+
+    match get_achievement_details(key, limit, &database.pool).await {
+        Ok(Some(details)) => Ok(Json::Ok(details)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
@@ -0,0 +1,80 @@
+use chrono::{Duration, Utc};
+use rocket::{get, http::Status, State};
+use serde_json::{json, Value};
+
+use crate::{ckey::Ckey, database::*, Database};
+
+use super::{common::ApiKey, Date, Json, Page, Strict};
+
+#[get("/admins")]
+pub async fn index(
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Vec<Admin>>, Status> {
+    // scoped keys only get the roster, last seen and playtime are for the main key
+    match get_admins(api_key.privileged, &database.pool).await {
+        Ok(admins) => Ok(Json::Ok(admins)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// the default window for activity when no range is given
+const ACTIVITY_DAYS: i64 = 30;
+
+#[get("/admins/activity?<from>&<to>")]
+pub async fn activity(
+    from: Option<Date>,
+    to: Option<Date>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    if !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    let to = to.map_or_else(|| Utc::now().date_naive(), |date| date.0);
+    let from = from.map_or_else(|| to - Duration::days(ACTIVITY_DAYS), |date| date.0);
+
+    if from > to {
+        return Err(Status::BadRequest);
+    }
+
+    match get_admin_activity(from, to, &database.pool).await {
+        Ok(admins) => Ok(Json::Ok(json!({
+            "from": from.format("%Y-%m-%d").to_string(),
+            "to": to.format("%Y-%m-%d").to_string(),
+            "admins": admins,
+        }))),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/admins/log?<admin>&<target>&<operation>&<from>&<to>&<page..>")]
+pub async fn log(
+    admin: Strict<Ckey>,
+    target: Option<&str>,
+    operation: Strict<AdminLogOperation>,
+    from: Strict<Date>,
+    to: Strict<Date>,
+    page: Page<u32>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    if !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    let filter = AdminLogFilter {
+        admin: admin.0.as_ref(),
+        target,
+        operation: operation.0,
+        from: from.0.map(|date| date.0),
+        to: to.0.map(|date| date.0),
+    };
+
+    match get_admin_log(&filter, page.after.0, page.limit, &database.pool).await {
+        Ok(entries) => Ok(page.respond(entries, |entry| entry.id)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
@@ -0,0 +1,100 @@
+use rand::{distributions::Alphanumeric, Rng as _};
+use rocket::{
+    get,
+    http::{Cookie, CookieJar, SameSite, Status},
+    post,
+    response::Redirect,
+    time::Duration,
+    State,
+};
+
+use crate::{config::Config, http::discord};
+
+use super::SESSION_COOKIE;
+
+const STATE_COOKIE: &str = "oauth_state";
+
+#[get("/auth/discord")]
+pub async fn login(cookies: &CookieJar<'_>, config: &State<Config>) -> Redirect {
+    let state: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    let cookie = Cookie::build((STATE_COOKIE, state.clone()))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::minutes(10));
+
+    cookies.add_private(cookie);
+
+    Redirect::to(discord::authorize_url(
+        config.discord.client_id,
+        &config.discord.redirect_uri,
+        &state,
+    ))
+}
+
+#[get("/auth/discord/callback?<code>&<state>&<error>")]
+pub async fn callback(
+    code: Option<&str>,
+    state: Option<&str>,
+    error: Option<&str>,
+    cookies: &CookieJar<'_>,
+    config: &State<Config>,
+) -> Result<Redirect, Status> {
+    let Some(expected_state) = cookies.get_private(STATE_COOKIE) else {
+        return Err(Status::BadRequest);
+    };
+
+    cookies.remove_private(STATE_COOKIE);
+
+    // discord redirects back with an error instead of a code when the user declines
+    match error {
+        Some("access_denied") => return Err(Status::Forbidden),
+        Some(_) => return Err(Status::BadRequest),
+        None => {}
+    }
+
+    let (Some(code), Some(state)) = (code, state) else {
+        return Err(Status::BadRequest);
+    };
+
+    if expected_state.value() != state {
+        return Err(Status::BadRequest);
+    }
+
+    let discord = &config.discord;
+
+    let Ok(token) = discord::exchange_code(
+        code,
+        discord.client_id,
+        &discord.client_secret,
+        &discord.redirect_uri,
+    )
+    .await
+    else {
+        return Err(Status::Unauthorized);
+    };
+
+    let Ok(user) = discord::get_current_user(&token.access_token).await else {
+        return Err(Status::InternalServerError);
+    };
+
+    let cookie = Cookie::build((SESSION_COOKIE, user.id))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::days(30));
+
+    cookies.add_private(cookie);
+
+    Ok(Redirect::to(discord.login_redirect.clone()))
+}
+
+#[post("/auth/logout")]
+pub async fn logout(cookies: &CookieJar<'_>) -> Status {
+    cookies.remove_private(SESSION_COOKIE);
+
+    Status::NoContent
+}
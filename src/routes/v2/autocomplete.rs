@@ -1,6 +1,6 @@
 use rocket::{get, http::Status, State};
 
-use crate::{database::*, Database};
+use crate::{ckey::Ckey, database::*, Database};
 
 use super::{common::ApiKey, Json};
 
@@ -19,11 +19,11 @@ pub async fn job(
 
 #[get("/autocomplete/ckey?<ckey>")]
 pub async fn ckey(
-    ckey: &str,
+    ckey: Ckey,
     database: &State<Database>,
     _api_key: ApiKey,
 ) -> Result<Json<Vec<String>>, Status> {
-    let Ok(ckeys) = get_ckeys(ckey, &database.pool).await else {
+    let Ok(ckeys) = get_ckeys(&ckey, &database.pool).await else {
         return Err(Status::InternalServerError);
     };
 
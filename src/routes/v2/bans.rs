@@ -0,0 +1,46 @@
+use rocket::{get, http::Status, State};
+use serde_json::Value;
+
+use crate::{ckey::Ckey, config::Config, database::*, Database};
+
+use super::{common::ApiKey, Date, Json, Page, Strict};
+
+#[allow(clippy::too_many_arguments)]
+#[get("/bans?<ckey>&<a_ckey>&<role>&<status>&<from>&<to>&<round_id>&<server>&<search>&<page..>")]
+pub async fn index(
+    ckey: Strict<Ckey>,
+    a_ckey: Strict<Ckey>,
+    role: Option<&str>,
+    status: Strict<BanStatus>,
+    from: Strict<Date>,
+    to: Strict<Date>,
+    round_id: Strict<u32>,
+    server: Option<&str>,
+    search: Option<&str>,
+    page: Page<u32>,
+    config: &State<Config>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let server_port = match server {
+        Some(server) => Some(config.server_port(server).ok_or(Status::BadRequest)?),
+        None => None,
+    };
+
+    let filter = BanFilter {
+        ckey: ckey.0.as_ref(),
+        a_ckey: a_ckey.0.as_ref(),
+        role,
+        status: status.0,
+        from: from.0.map(|date| date.0),
+        to: to.0.map(|date| date.0),
+        round_id: round_id.0,
+        server_port,
+        search,
+    };
+
+    match search_bans(&filter, page.after.0, page.limit, &database.pool).await {
+        Ok(bans) => Ok(page.respond(bans, |ban| ban.ids.first().copied())),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
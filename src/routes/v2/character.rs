@@ -0,0 +1,29 @@
+use rocket::{get, http::Status, State};
+use serde_json::{json, Value};
+
+use crate::{config::Config, database::*, Database};
+
+use super::{common::ApiKey, Json};
+
+#[get("/character?<name>")]
+pub async fn index(
+    name: &str,
+    database: &State<Database>,
+    config: &State<Config>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(Status::BadRequest);
+    }
+
+    match get_character_players(name, &config.excluded_roles, &database.pool).await {
+        Ok(players) if players.is_empty() => Err(Status::NotFound),
+        Ok(players) => Ok(Json::Ok(json!({
+            "name": name,
+            "players": players,
+        }))),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
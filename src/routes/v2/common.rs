@@ -1,12 +1,15 @@
 use std::io::Cursor;
 
+use chrono::NaiveDate;
 use rocket::{
+    form::{self, error::ErrorKind, DataField, FromForm, FromFormField, ValueField},
     http::{ContentType, Status},
     request::{FromRequest, Outcome},
     response::{self, Responder, Response},
     Request,
 };
 use serde::Serialize;
+use serde_json::{json, Value};
 
 use crate::config::Config;
 
@@ -35,7 +38,10 @@ impl<R: Serialize> Responder<'_, 'static> for Json<R> {
     }
 }
 
-pub struct ApiKey;
+pub struct ApiKey {
+    // only the main secret is privileged, dev and exposed keys are scoped to their routes
+    pub privileged: bool,
+}
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for ApiKey {
@@ -47,13 +53,13 @@ impl<'r> FromRequest<'r> for ApiKey {
         };
 
         if request.headers().get_one("X-API-KEY") == Some(&config.secret) {
-            return Outcome::Success(ApiKey);
+            return Outcome::Success(ApiKey { privileged: true });
         }
 
         if request.headers().get_one("X-DEV-KEY") == Some(&config.dev_secret) {
             if let Some(route) = request.route() {
                 if config.dev_routes.contains(route.uri.origin.path().as_str()) {
-                    return Outcome::Success(ApiKey);
+                    return Outcome::Success(ApiKey { privileged: false });
                 }
             }
         }
@@ -64,7 +70,7 @@ impl<'r> FromRequest<'r> for ApiKey {
                     .exposed_routes
                     .contains(route.uri.origin.path().as_str())
                 {
-                    return Outcome::Success(ApiKey);
+                    return Outcome::Success(ApiKey { privileged: false });
                 }
             }
         }
@@ -72,3 +78,183 @@ impl<'r> FromRequest<'r> for ApiKey {
         Outcome::Error((Status::Unauthorized, ()))
     }
 }
+
+pub const SESSION_COOKIE: &str = "session";
+
+pub struct Session {
+    pub discord_id: i64,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Session {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(cookie) = request.cookies().get_private(SESSION_COOKIE) else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+
+        let Ok(discord_id) = cookie.value().parse() else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+
+        Outcome::Success(Session { discord_id })
+    }
+}
+
+pub struct Date(pub NaiveDate);
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Date {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        match NaiveDate::parse_from_str(field.value, "%Y-%m-%d") {
+            Ok(date) => Ok(Date(date)),
+            Err(e) => Err(form::Error::validation(e.to_string()))?,
+        }
+    }
+}
+
+// an optional field that fails to parse is read as absent by `Option`, this keeps the error
+#[derive(Debug)]
+pub struct Strict<T>(pub Option<T>);
+
+#[rocket::async_trait]
+impl<'v, T: FromForm<'v>> FromForm<'v> for Strict<T> {
+    type Context = T::Context;
+
+    fn init(_: form::Options) -> Self::Context {
+        T::init(form::Options { strict: true })
+    }
+
+    fn push_value(ctxt: &mut Self::Context, field: ValueField<'v>) {
+        T::push_value(ctxt, field)
+    }
+
+    async fn push_data(ctxt: &mut Self::Context, field: DataField<'v, '_>) {
+        T::push_data(ctxt, field).await
+    }
+
+    fn finalize(this: Self::Context) -> form::Result<'v, Self> {
+        match T::finalize(this) {
+            Ok(value) => Ok(Strict(Some(value))),
+            Err(errors) if errors.iter().all(|e| matches!(e.kind, ErrorKind::Missing)) => {
+                Ok(Strict(None))
+            }
+            Err(errors) => Err(errors),
+        }
+    }
+}
+
+// `after` and `limit` of the cursor paginated listings, taken with `<page..>`
+#[derive(Debug, FromForm)]
+pub struct Page<C> {
+    pub after: Strict<C>,
+    #[field(default = 25, validate = range(1..=100))]
+    pub limit: u32,
+}
+
+impl<C> Page<C> {
+    // a full page may have more behind it, so the last item becomes the next cursor
+    pub fn respond<T: Serialize, N: Serialize>(
+        &self,
+        data: Vec<T>,
+        cursor: impl FnOnce(&T) -> N,
+    ) -> Json<Value> {
+        Json::Ok(self.body(data, cursor))
+    }
+
+    pub fn body<T: Serialize, N: Serialize>(
+        &self,
+        data: Vec<T>,
+        cursor: impl FnOnce(&T) -> N,
+    ) -> Value {
+        let next_cursor = match data.len() as u32 == self.limit {
+            true => data.last().map(cursor),
+            false => None,
+        };
+
+        json!({
+            "data": data,
+            "next_cursor": next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{get, local::blocking::Client, routes};
+
+    use super::*;
+
+    #[get("/?<date>&<page..>")]
+    fn listing(date: Strict<Date>, page: Page<u32>) -> String {
+        format!(
+            "{:?} {:?} {}",
+            date.0.map(|date| date.0),
+            page.after.0,
+            page.limit
+        )
+    }
+
+    fn get(uri: &str) -> (Status, Option<String>) {
+        let client = Client::tracked(rocket::build().mount("/", routes![listing])).unwrap();
+        let response = client.get(uri).dispatch();
+
+        (response.status(), response.into_string())
+    }
+
+    #[test]
+    fn absent_fields_are_none() {
+        assert_eq!(get("/"), (Status::Ok, Some("None None 25".to_string())));
+        assert_eq!(
+            get("/?date=2024-01-31&after=5&limit=10"),
+            (Status::Ok, Some("Some(2024-01-31) Some(5) 10".to_string()))
+        );
+    }
+
+    #[test]
+    fn malformed_fields_are_rejected() {
+        for uri in [
+            "/?date=2024-13-40",
+            "/?date=",
+            "/?after=abc",
+            "/?limit=0",
+            "/?limit=101",
+        ] {
+            assert_eq!(get(uri).0, Status::UnprocessableEntity, "{uri}");
+        }
+    }
+
+    fn page(limit: u32) -> Page<u32> {
+        Page {
+            after: Strict(None),
+            limit,
+        }
+    }
+
+    #[test]
+    fn full_page_has_next_cursor() {
+        let body = page(3).body(vec![10, 9, 8], |id| *id);
+
+        assert_eq!(body["data"], json!([10, 9, 8]));
+        assert_eq!(body["next_cursor"], json!(8));
+    }
+
+    #[test]
+    fn short_page_is_the_last() {
+        let body = page(3).body(vec![10, 9], |id| *id);
+        assert_eq!(body["next_cursor"], Value::Null);
+
+        let body = page(3).body(Vec::<u32>::new(), |id| *id);
+        assert_eq!(body["data"], json!([]));
+        assert_eq!(body["next_cursor"], Value::Null);
+    }
+
+    #[test]
+    fn cursor_is_taken_from_the_last_item() {
+        let body = page(2).body(vec![(5, "a"), (4, "b")], |(id, name)| {
+            format!("{id}_{name}")
+        });
+        assert_eq!(body["next_cursor"], json!("4_b"));
+    }
+}
@@ -1,9 +1,9 @@
 use rocket::{get, http::Status, State};
 use serde_json::{json, Value};
 
-use crate::{database::*, Config, Database};
+use crate::{ckey::Ckey, database::*, Config, Database};
 
-use super::{common::ApiKey, Json};
+use super::{common::ApiKey, Date, Json, Page, Strict};
 
 #[get("/events/overview?<limit>")]
 pub async fn overview(
@@ -18,21 +18,76 @@ pub async fn overview(
     }
 }
 
-#[get("/events/deaths?<fetch_size>&<page>")]
+#[allow(clippy::too_many_arguments)]
+#[get("/events/deaths?<round_id>&<job>&<special>&<pod>&<suicide>&<damage>&<from>&<to>&<ckey>&<sort>&<count>&<page>&<fetch_size>&<paging..>")]
 pub async fn deaths(
-    fetch_size: Option<i32>,
-    page: Option<i32>,
+    round_id: Strict<u32>,
+    job: Option<&str>,
+    special: Option<&str>,
+    pod: Option<&str>,
+    suicide: Strict<bool>,
+    damage: Strict<DamageType>,
+    from: Strict<Date>,
+    to: Strict<Date>,
+    ckey: Strict<Ckey>,
+    sort: Strict<DeathSort>,
+    count: Strict<bool>,
+    page: Option<&str>,
+    fetch_size: Option<&str>,
+    paging: Page<String>,
     config: &State<Config>,
     database: &State<Database>,
-    _api_key: ApiKey,
+    api_key: ApiKey,
 ) -> Result<Json<Value>, Status> {
-    match get_deaths(fetch_size, page, config, &database.pool).await {
-        Ok((deaths, total_count)) => Ok(Json::Ok(json!({
-            "data": deaths,
-            "total_count": total_count
-        }))),
-        Err(_) => Err(Status::InternalServerError),
+    // offset paging was replaced by the cursor, old clients get an error rather than page one
+    if page.is_some() || fetch_size.is_some() {
+        return Err(Status::BadRequest);
+    }
+
+    // deaths are public, who died is not
+    if ckey.0.is_some() && !api_key.privileged {
+        return Err(Status::Forbidden);
     }
+
+    let sort = sort.0.unwrap_or_default();
+
+    let after = match &paging.after.0 {
+        Some(after) => Some(DeathCursor::parse(after, sort).ok_or(Status::BadRequest)?),
+        None => None,
+    };
+
+    let filter = DeathFilter {
+        round_id: round_id.0,
+        job,
+        special,
+        pod,
+        suicide: suicide.0,
+        damage: damage.0,
+        from: from.0.map(|date| date.0),
+        to: to.0.map(|date| date.0),
+        ckey: ckey.0.as_ref(),
+    };
+
+    if matches!(sort, DeathSort::Damage) && !filter.is_narrowed() {
+        return Err(Status::BadRequest);
+    }
+
+    let Ok(deaths) = get_deaths(&filter, sort, after, paging.limit, config, &database.pool).await
+    else {
+        return Err(Status::InternalServerError);
+    };
+
+    let mut response = paging.body(deaths, |death| DeathCursor::of(death, sort));
+
+    if count.0.unwrap_or(false) {
+        let Ok(total_count) = count_deaths(&filter, config, &database.pool).await else {
+            return Err(Status::InternalServerError);
+        };
+
+        response["total_count"] = json!(total_count);
+    }
+
+    Ok(Json::Ok(response))
 }
 
 #[get("/events/citations?<fetch_size>&<page>")]
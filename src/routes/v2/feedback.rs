@@ -0,0 +1,88 @@
+use rocket::{get, http::Status, State};
+use serde_json::{json, Value};
+
+use crate::{
+    database::{error::Error, *},
+    Config, Database,
+};
+
+use super::{common::ApiKey, Json};
+
+#[get("/feedback/keys")]
+pub async fn keys(
+    config: &State<Config>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<FeedbackKey>>, Status> {
+    match get_feedback_keys(config, &database.pool).await {
+        Ok(keys) => Ok(Json::Ok(keys)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/feedback?<key>&<type>&<round>&<from>&<to>&<aggregate>&<limit>")]
+pub async fn index(
+    key: &str,
+    r#type: Option<&str>,
+    round: Option<u32>,
+    from: Option<u32>,
+    to: Option<u32>,
+    aggregate: Option<bool>,
+    limit: Option<u32>,
+    config: &State<Config>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let limit = limit.unwrap_or(100);
+
+    if !(1..=1000).contains(&limit) {
+        return Err(Status::BadRequest);
+    }
+
+    // a single round is just a range of one
+    let (from, to) = match round {
+        Some(_) if from.is_some() || to.is_some() => return Err(Status::BadRequest),
+        Some(round) => (Some(round), Some(round)),
+        None => (from, to),
+    };
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(Status::BadRequest);
+        }
+    }
+
+    let filter = FeedbackFilter {
+        key_name: key,
+        key_type: r#type,
+        from_round: from,
+        to_round: to,
+    };
+
+    let feedbacks = match search_feedback(&filter, limit, config, &database.pool).await {
+        Ok(feedbacks) => feedbacks,
+        Err(_) => return Err(Status::InternalServerError),
+    };
+
+    let mut response = json!({
+        "count": feedbacks.len(),
+    });
+
+    // aggregates cover the whole range, not only the page of entries returned
+    if aggregate.unwrap_or(false) {
+        match aggregate_feedback(&filter, config, &database.pool).await {
+            Ok(aggregate) => {
+                response["aggregated"] =
+                    json!(aggregate.as_ref().map_or(0, |aggregate| aggregate.entries));
+                response["aggregate"] = json!(aggregate.map(|aggregate| aggregate.value));
+            }
+            Err(Error::FeedbackNotAggregatable) => return Err(Status::BadRequest),
+            Err(_) => return Err(Status::InternalServerError),
+        }
+    }
+
+    response["data"] = json!(feedbacks);
+
+    Ok(Json::Ok(response))
+}
@@ -0,0 +1,60 @@
+use rocket::{get, http::Status, State};
+use serde_json::Value;
+
+use crate::{database::*, Database};
+
+use super::{common::ApiKey, Json, Page, Strict};
+
+#[allow(clippy::too_many_arguments)]
+#[get("/library?<search>&<title>&<author>&<category>&<deleted>&<page..>")]
+pub async fn index(
+    search: Option<&str>,
+    title: Option<&str>,
+    author: Option<&str>,
+    category: Strict<BookCategory>,
+    deleted: Strict<bool>,
+    page: Page<u32>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let include_deleted = deleted.0.unwrap_or(false);
+
+    if include_deleted && !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    let filter = BookFilter {
+        search,
+        title,
+        author,
+        category: category.0,
+        include_deleted,
+    };
+
+    let books = get_books(
+        &filter,
+        api_key.privileged,
+        page.after.0,
+        page.limit,
+        &database.pool,
+    )
+    .await;
+
+    match books {
+        Ok(books) => Ok(page.respond(books, |book| book.id)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/library/<id>")]
+pub async fn book(
+    id: u32,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Book>, Status> {
+    match get_book(id, api_key.privileged, &database.pool).await {
+        Ok(Some(book)) => Ok(Json::Ok(book)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
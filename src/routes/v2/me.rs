@@ -0,0 +1,47 @@
+use rocket::{get, http::Status, post, State};
+
+use crate::{
+    database::{error::Error, *},
+    Database,
+};
+
+use super::{common::Session, Json};
+
+#[get("/me")]
+pub async fn index(session: Session, database: &State<Database>) -> Result<Json<Player>, Status> {
+    let ckey = match get_ckey_by_discord_id(&session.discord_id.to_string(), &database.pool).await {
+        Ok(ckey) => ckey,
+        Err(Error::NotLinked) => return Err(Status::Conflict),
+        Err(_) => return Err(Status::InternalServerError),
+    };
+
+    match get_player(&ckey, &database.pool).await {
+        Ok(player) => Ok(Json::Ok(player)),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/me/bans")]
+pub async fn bans(session: Session, database: &State<Database>) -> Result<Json<Vec<Ban>>, Status> {
+    let ckey = match get_ckey_by_discord_id(&session.discord_id.to_string(), &database.pool).await {
+        Ok(ckey) => ckey,
+        Err(Error::NotLinked) => return Err(Status::Conflict),
+        Err(_) => return Err(Status::InternalServerError),
+    };
+
+    match get_ban(&ckey, false, None, &database.pool).await {
+        Ok(bans) => Ok(Json::Ok(bans)),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[post("/me/unlink")]
+pub async fn unlink(session: Session, database: &State<Database>) -> Result<Json<String>, Status> {
+    match unverify_discord(Some(&session.discord_id.to_string()), None, &database.pool).await {
+        Ok(ckey) => Ok(Json::Ok(ckey)),
+        Err(Error::NotLinked) => Err(Status::Conflict),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
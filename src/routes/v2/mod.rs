@@ -1,32 +1,71 @@
 use rocket::{routes, Build, Rocket};
 
+mod achievements;
+mod admins;
+mod auth;
 mod autocomplete;
+mod bans;
 mod byond;
+mod character;
 mod common;
 mod discord;
 mod events;
+mod feedback;
+mod library;
+mod me;
 mod patreon;
 mod player;
+mod polls;
+mod round;
 mod server;
+mod stickybans;
 mod verify;
 
 pub use common::*;
 
 pub fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
-    rocket.mount(
+    rocket.attach(patreon::refresher()).mount(
         "/v2",
         routes![
             patreon::index,
             patreon::patrons,
+            patreon::webhook,
+            patreon::link,
             player::index,
             player::ban,
+            player::active_ban,
+            player::stickybans,
             player::characters,
             player::roletime,
             player::activity,
+            player::heatmap,
             player::top,
+            player::leaderboard,
+            player::roletime_history,
             player::discord,
+            player::discord_bulk,
             player::achievements,
+            player::notes,
+            player::related,
+            player::profile,
             server::index,
+            character::index,
+            achievements::index,
+            achievements::achievement,
+            admins::index,
+            admins::activity,
+            admins::log,
+            polls::index,
+            polls::poll,
+            stickybans::index,
+            stickybans::stickyban,
+            library::index,
+            library::book,
+            round::index,
+            round::list,
+            round::stats,
+            feedback::keys,
+            feedback::index,
             verify::index,
             verify::unverify,
             discord::user,
@@ -38,6 +77,13 @@ pub fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
             events::overview,
             events::citations,
             events::deaths,
+            auth::login,
+            auth::callback,
+            auth::logout,
+            me::index,
+            me::bans,
+            me::unlink,
+            bans::index,
         ],
     )
 }
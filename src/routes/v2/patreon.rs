@@ -1,9 +1,33 @@
-use rocket::{get, http::Status, State};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac as _};
+use md5::Md5;
+use once_cell::sync::Lazy;
+use rocket::{
+    data::ToByteUnit as _,
+    fairing::AdHoc,
+    get,
+    http::Status,
+    post,
+    request::{FromRequest, Outcome},
+    serde::json,
+    Data, Request, State,
+};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use sqlx::MySqlPool;
+use tokio::sync::RwLock;
+use tracing::error;
 
 use crate::{
-    config::{self, Config},
+    ckey::Ckey,
+    config::{self, Config, PatreonTier},
     database::{error::Error, *},
     http::{
         self,
@@ -16,23 +40,74 @@ use super::{common::ApiKey, Json};
 
 #[get("/patreon?<ckey>")]
 pub async fn index(
-    ckey: &str,
+    ckey: Ckey,
     database: &State<Database>,
     config: &State<Config>,
     _api_key: ApiKey,
 ) -> Result<Json<Value>, Status> {
-    let Ok(patron) = is_patron(ckey, &database.pool, &config.discord).await else {
+    let Ok((patron, tier)) = get_patron_tier(&ckey, &database.pool, &config.discord).await else {
         return Err(Status::InternalServerError);
     };
 
-    Ok(Json::Ok(json!({ "patron": patron })))
+    Ok(Json::Ok(json!({
+        "patron": patron,
+        "tier": tier.map(tier_to_json),
+    })))
+}
+
+pub(super) fn tier_to_json(tier: &PatreonTier) -> Value {
+    json!({
+        "name": tier.name,
+        "rank": tier.rank,
+        "perks": tier.perks,
+    })
+}
+
+fn highest_tier<'a>(
+    roles: &HashSet<String>,
+    discord: &'a config::Discord,
+) -> Option<&'a PatreonTier> {
+    discord
+        .patreon_tiers
+        .iter()
+        .filter(|tier| roles.contains(&tier.role.to_string()))
+        .max_by_key(|tier| tier.rank)
+}
+
+fn highest_pledge_tier<'a>(
+    pledge: &Pledge,
+    discord: &'a config::Discord,
+) -> Option<&'a PatreonTier> {
+    discord
+        .patreon_tiers
+        .iter()
+        .filter(|tier| {
+            tier.tier_id
+                .as_ref()
+                .is_some_and(|tier_id| pledge.tier_ids.contains(tier_id))
+        })
+        .max_by_key(|tier| tier.rank)
 }
 
-async fn is_patron(ckey: &str, pool: &MySqlPool, discord: &config::Discord) -> Result<bool, Error> {
+pub(super) async fn get_patron_tier<'a>(
+    ckey: &Ckey,
+    pool: &MySqlPool,
+    discord: &'a config::Discord,
+) -> Result<(bool, Option<&'a PatreonTier>), Error> {
     let mut connection = pool.acquire().await?;
 
+    let pledge = match pledge_by_ckey(ckey, &mut connection).await {
+        Ok(pledge) if pledge.is_active() => Some(pledge),
+        Ok(_) | Err(Error::NotLinked) => None,
+        Err(e) => return Err(e),
+    };
+
+    let pledge_tier = pledge
+        .as_ref()
+        .and_then(|pledge| highest_pledge_tier(pledge, discord));
+
     let Ok(discord_id) = discord_id_by_ckey(ckey, &mut connection).await else {
-        return Ok(false);
+        return Ok((pledge.is_some(), pledge_tier));
     };
 
     connection.close().await?;
@@ -40,13 +115,201 @@ async fn is_patron(ckey: &str, pool: &MySqlPool, discord: &config::Discord) -> R
     let member = match get_guild_member(discord.guild, discord_id, &discord.token).await {
         Ok(member) => member,
         Err(http::Error::Discord(code)) => match code {
-            10007 | 10013 => return Ok(false),
+            10007 | 10013 => return Ok((pledge.is_some(), pledge_tier)),
             _ => return Err(http::Error::Discord(code))?,
         },
         Err(e) => return Err(e)?,
     };
 
-    Ok(member.roles.contains(&discord.patreon_role.to_string()))
+    let tier = [highest_tier(&member.roles, discord), pledge_tier]
+        .into_iter()
+        .flatten()
+        .max_by_key(|tier| tier.rank);
+
+    Ok((tier.is_some() || pledge.is_some(), tier))
+}
+
+pub struct PatreonHeaders<'r> {
+    signature: &'r str,
+    event: &'r str,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PatreonHeaders<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        match (
+            headers.get_one("X-Patreon-Signature"),
+            headers.get_one("X-Patreon-Event"),
+        ) {
+            (Some(signature), Some(event)) => Outcome::Success(PatreonHeaders { signature, event }),
+            _ => Outcome::Error((Status::BadRequest, ())),
+        }
+    }
+}
+
+#[post("/patreon/webhook", data = "<data>")]
+pub async fn webhook(
+    headers: PatreonHeaders<'_>,
+    data: Data<'_>,
+    database: &State<Database>,
+    config: &State<Config>,
+) -> Status {
+    // an empty key would let anyone sign their own pledge events
+    if config.patreon.webhook_secret.is_empty() {
+        return Status::ServiceUnavailable;
+    }
+
+    let Ok(body) = data.open(1.mebibytes()).into_string().await else {
+        return Status::BadRequest;
+    };
+
+    if !body.is_complete() {
+        return Status::PayloadTooLarge;
+    }
+
+    if !verify_signature(&config.patreon.webhook_secret, &body, headers.signature) {
+        return Status::Unauthorized;
+    }
+
+    let deleted = match headers.event {
+        "members:pledge:create" | "members:pledge:update" => false,
+        "members:pledge:delete" => true,
+        _ => return Status::NoContent,
+    };
+
+    let Ok(payload) = serde_json::from_str::<Value>(&body) else {
+        return Status::BadRequest;
+    };
+
+    let Some(pledge) = pledge_from_payload(&payload, headers.event, deleted) else {
+        return Status::BadRequest;
+    };
+
+    match record_pledge_event(&pledge, &database.pool).await {
+        Ok(()) => Status::NoContent,
+        Err(_) => Status::InternalServerError,
+    }
+}
+
+fn verify_signature(secret: &str, body: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Md5>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+
+    mac.update(body.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn pledge_from_payload(payload: &Value, event: &str, deleted: bool) -> Option<Pledge> {
+    // https://docs.patreon.com/#webhooks
+    let member = &payload["data"];
+    let attributes = &member["attributes"];
+    let relationships = &member["relationships"];
+
+    let patreon_id = relationships["user"]["data"]["id"].as_str()?.to_string();
+
+    let tier_ids = relationships["currently_entitled_tiers"]["data"]
+        .as_array()
+        .map(|tiers| {
+            tiers
+                .iter()
+                .filter_map(|tier| tier["id"].as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let pledge = Pledge {
+        patreon_id,
+        ckey: None,
+        full_name: attributes["full_name"].as_str().map(str::to_string),
+        patron_status: match deleted {
+            true => Some("former_patron".to_string()),
+            false => attributes["patron_status"].as_str().map(str::to_string),
+        },
+        entitled_cents: match deleted {
+            true => 0,
+            false => attributes["currently_entitled_amount_cents"]
+                .as_u64()
+                .unwrap_or(0) as u32,
+        },
+        tier_ids: match deleted {
+            true => Vec::new(),
+            false => tier_ids,
+        },
+        last_event: event.to_string(),
+        updated_at: Utc::now().naive_utc(),
+    };
+
+    Some(pledge)
+}
+
+#[derive(Deserialize)]
+pub struct LinkData<'r> {
+    patreon_id: &'r str,
+    ckey: Ckey,
+}
+
+#[post("/patreon/link", data = "<data>")]
+pub async fn link(
+    data: json::Json<LinkData<'_>>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<String>, Status> {
+    match link_pledge(data.patreon_id, &data.ckey, &database.pool).await {
+        Ok(()) => Ok(Json::Ok(data.ckey.to_string())),
+        Err(Error::PledgeInUse(patreon_id)) => Ok(Json::Conflict(patreon_id)),
+        Err(Error::PledgeLinked(ckey)) => Ok(Json::Conflict(ckey)),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(Error::PledgeNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Patron {
+    ckey: String,
+    // role id of the tier, names can be changed in the config
+    tier: i64,
+}
+
+type PatronsCache = Option<(NaiveDateTime, Vec<Patron>)>;
+
+static LAST_PATRONS: Lazy<Arc<RwLock<PatronsCache>>> = Lazy::new(|| Arc::new(RwLock::new(None)));
+
+pub fn refresher() -> AdHoc {
+    AdHoc::on_liftoff("Patrons Refresher", |rocket| {
+        Box::pin(async move {
+            let (Some(config), Some(database)) =
+                (rocket.state::<Config>(), rocket.state::<Database>())
+            else {
+                return;
+            };
+
+            let discord = config.discord.clone();
+            let pool = database.pool.clone();
+
+            tokio::spawn(async move {
+                let period = Duration::from_secs(discord.patrons_refresh_interval.max(1));
+                let mut interval = tokio::time::interval(period);
+
+                loop {
+                    interval.tick().await;
+
+                    if let Err(e) = refresh_patrons(&pool, &discord).await {
+                        error!("Failed to refresh patrons: {e}");
+                    }
+                }
+            });
+        })
+    })
 }
 
 #[get("/patreon/patrons")]
@@ -55,34 +318,215 @@ pub async fn patrons(
     config: &State<Config>,
     _api_key: ApiKey,
 ) -> Result<Json<Value>, Status> {
-    let Ok(patrons) = get_patrons(&database.pool, &config.discord).await else {
+    let Ok((last_refreshed, patrons)) = get_patrons(&database.pool, &config.discord).await else {
         return Err(Status::InternalServerError);
     };
 
-    Ok(Json::Ok(json!({ "patrons": patrons })))
+    let mut members: HashMap<i64, Vec<&str>> = HashMap::new();
+
+    for patron in &patrons {
+        members.entry(patron.tier).or_default().push(&patron.ckey);
+    }
+
+    let mut tiers = config.discord.patreon_tiers.iter().collect::<Vec<_>>();
+    tiers.sort_by_key(|tier| Reverse(tier.rank));
+
+    let tiers = tiers
+        .into_iter()
+        .map(|tier| {
+            json!({
+                "id": tier.role.to_string(),
+                "name": tier.name,
+                "rank": tier.rank,
+                "ckeys": members.remove(&tier.role).unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let ckeys = patrons
+        .iter()
+        .map(|patron| &patron.ckey)
+        .collect::<Vec<_>>();
+
+    Ok(Json::Ok(json!({
+        "patrons": ckeys,
+        "tiers": tiers,
+        "last_refreshed": last_refreshed.format("%Y-%m-%d %H:%M:%S").to_string(),
+    })))
 }
 
-async fn get_patrons(pool: &MySqlPool, discord: &config::Discord) -> Result<Vec<String>, Error> {
-    let mut connection = pool.acquire().await?;
+async fn get_patrons(
+    pool: &MySqlPool,
+    discord: &config::Discord,
+) -> Result<(NaiveDateTime, Vec<Patron>), Error> {
+    {
+        let last_patrons = LAST_PATRONS.read().await;
+        if let Some((last_refreshed, patrons)) = &*last_patrons {
+            return Ok((*last_refreshed, patrons.clone()));
+        }
+    }
 
-    let query = format!(
-        "{{\"or_query\":{{}},\"and_query\":{{\"role_ids\":{{\"and_query\":[\"{}\"]}}}},\"limit\":1000}}",
-        discord.patreon_role
-    );
+    refresh_patrons(pool, discord).await
+}
+
+async fn refresh_patrons(
+    pool: &MySqlPool,
+    discord: &config::Discord,
+) -> Result<(NaiveDateTime, Vec<Patron>), Error> {
+    let role_ids = discord
+        .patreon_tiers
+        .iter()
+        .map(|tier| tier.role.to_string())
+        .collect::<Vec<_>>();
+
+    let query = json!({
+        "or_query": {},
+        "and_query": {
+            "role_ids": {
+                "or_query": role_ids,
+            },
+        },
+    });
 
     let members = search_members(discord.guild, query, &discord.token).await?;
 
-    let mut ckeys = Vec::new();
+    let discord_ids = members
+        .iter()
+        .map(|member| member.user.id.clone())
+        .collect::<Vec<_>>();
+
+    let mut connection = pool.acquire().await?;
+
+    let ckeys = ckeys_by_discord_ids(&discord_ids, &mut connection).await?;
+
+    connection.close().await?;
+
+    let mut patrons = Vec::new();
+
+    for member in &members {
+        let Some(tier) = highest_tier(&member.roles, discord) else {
+            continue;
+        };
 
-    for member in members {
-        match ckey_by_discord_id(&member.user.id, &mut connection).await {
-            Ok(ckey) => ckeys.push(ckey),
-            Err(Error::NotLinked) => continue,
-            Err(e) => return Err(e),
+        if let Some(ckey) = ckeys.get(&member.user.id) {
+            patrons.push(Patron {
+                ckey: ckey.clone(),
+                tier: tier.role,
+            });
         }
     }
 
-    connection.close().await?;
+    let last_refreshed = Utc::now().naive_utc();
+
+    let mut last_patrons = LAST_PATRONS.write().await;
+    *last_patrons = Some((last_refreshed, patrons.clone()));
+
+    Ok((last_refreshed, patrons))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(role: i64, rank: u32, tier_id: Option<&str>) -> PatreonTier {
+        PatreonTier {
+            role,
+            name: format!("Tier {rank}"),
+            rank,
+            tier_id: tier_id.map(str::to_string),
+            perks: Vec::new(),
+        }
+    }
 
-    Ok(ckeys)
+    fn discord(patreon_tiers: Vec<PatreonTier>) -> config::Discord {
+        config::Discord {
+            token: String::new(),
+            guild: 0,
+            patreon_tiers,
+            patrons_refresh_interval: 0,
+            client_id: 0,
+            client_secret: String::new(),
+            redirect_uri: String::new(),
+            login_redirect: String::new(),
+        }
+    }
+
+    fn roles(roles: &[&str]) -> HashSet<String> {
+        roles.iter().map(|role| role.to_string()).collect()
+    }
+
+    #[test]
+    fn picks_highest_ranked_role() {
+        let discord = discord(vec![tier(1, 1, None), tier(2, 3, None), tier(3, 2, None)]);
+
+        let tier = highest_tier(&roles(&["1", "3", "99"]), &discord).unwrap();
+        assert_eq!(tier.role, 3);
+
+        let tier = highest_tier(&roles(&["1", "2", "3"]), &discord).unwrap();
+        assert_eq!(tier.role, 2);
+    }
+
+    #[test]
+    fn no_tier_without_a_tier_role() {
+        let discord = discord(vec![tier(1, 1, None)]);
+
+        assert!(highest_tier(&roles(&[]), &discord).is_none());
+        assert!(highest_tier(&roles(&["2"]), &discord).is_none());
+    }
+
+    fn pledge(tier_ids: &[&str]) -> Pledge {
+        Pledge {
+            patreon_id: "1".to_string(),
+            ckey: None,
+            full_name: None,
+            patron_status: Some("active_patron".to_string()),
+            entitled_cents: 500,
+            tier_ids: tier_ids.iter().map(|id| id.to_string()).collect(),
+            last_event: "members:pledge:create".to_string(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn picks_highest_ranked_pledge_tier() {
+        let discord = discord(vec![
+            tier(1, 1, Some("a")),
+            tier(2, 2, Some("b")),
+            tier(3, 3, None),
+        ]);
+
+        let tier = highest_pledge_tier(&pledge(&["a", "b"]), &discord).unwrap();
+        assert_eq!(tier.role, 2);
+
+        assert!(highest_pledge_tier(&pledge(&["c"]), &discord).is_none());
+        assert!(highest_pledge_tier(&pledge(&[]), &discord).is_none());
+    }
+
+    #[test]
+    fn verifies_signature() {
+        // RFC 2104 HMAC-MD5 test vector
+        let signature = "750c783e6ab0b503eaa86e310a5db738";
+
+        assert!(verify_signature(
+            "Jefe",
+            "what do ya want for nothing?",
+            signature
+        ));
+        assert!(!verify_signature(
+            "Jefe",
+            "what do ya want for something?",
+            signature
+        ));
+        assert!(!verify_signature(
+            "other",
+            "what do ya want for nothing?",
+            signature
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_signature() {
+        assert!(!verify_signature("Jefe", "body", "not hex"));
+        assert!(!verify_signature("Jefe", "body", ""));
+    }
 }
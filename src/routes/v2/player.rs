@@ -1,21 +1,31 @@
-use rocket::{get, http::Status, State};
+use std::{collections::HashSet, future::Future};
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use rocket::{get, http::Status, post, serde::json, State};
+use serde::Deserialize;
 use serde_json::{json, Value};
 
 use crate::{
+    ckey::Ckey,
     config::Config,
     database::{error::Error, *},
     Database,
 };
 
-use super::{common::ApiKey, Json};
+use super::{
+    common::ApiKey,
+    patreon::{get_patron_tier, tier_to_json},
+    Date, Json, Strict,
+};
 
 #[get("/player?<ckey>")]
 pub async fn index(
-    ckey: &str,
+    ckey: Ckey,
     database: &State<Database>,
     _api_key: ApiKey,
 ) -> Result<Json<Player>, Status> {
-    match get_player(ckey, &database.pool).await {
+    match get_player(&ckey, &database.pool).await {
         Ok(player) => Ok(Json::Ok(player)),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(_) => Err(Status::InternalServerError),
@@ -24,26 +34,61 @@ pub async fn index(
 
 #[get("/player/ban?<ckey>&<permanent>&<since>")]
 pub async fn ban(
-    ckey: &str,
+    ckey: Ckey,
     permanent: Option<bool>,
     since: Option<&str>,
     database: &State<Database>,
     _api_key: ApiKey,
 ) -> Result<Json<Vec<Ban>>, Status> {
-    match get_ban(ckey, permanent.unwrap_or(false), since, &database.pool).await {
+    match get_ban(&ckey, permanent.unwrap_or(false), since, &database.pool).await {
         Ok(bans) => Ok(Json::Ok(bans)),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(_) => Err(Status::InternalServerError),
     }
 }
 
+#[get("/player/ban/active?<ckey>&<role>")]
+pub async fn active_ban(
+    ckey: Ckey,
+    role: Option<&str>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    match get_active_bans(&ckey, role, &database.pool).await {
+        Ok(bans) => Ok(Json::Ok(json!({
+            "banned": !bans.is_empty(),
+            "bans": bans,
+        }))),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/player/stickybans?<ckey>")]
+pub async fn stickybans(
+    ckey: Ckey,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Vec<MatchedStickyban>>, Status> {
+    if !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    match get_matched_stickybans(&ckey, &database.pool).await {
+        Ok(stickybans) => Ok(Json::Ok(stickybans)),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
 #[get("/player/characters?<ckey>")]
 pub async fn characters(
-    ckey: &str,
+    ckey: Ckey,
     database: &State<Database>,
+    config: &State<Config>,
     _api_key: ApiKey,
 ) -> Result<Json<Vec<(String, i64)>>, Status> {
-    match get_characters(ckey, &database.pool).await {
+    match get_characters(&ckey, &config.excluded_roles, &database.pool).await {
         Ok(characters) => Ok(Json::Ok(characters)),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(_) => Err(Status::InternalServerError),
@@ -52,17 +97,43 @@ pub async fn characters(
 
 #[get("/player/roletime?<ckey>")]
 pub async fn roletime(
-    ckey: &str,
+    ckey: Ckey,
     database: &State<Database>,
     _api_key: ApiKey,
 ) -> Result<Json<Vec<PlayerRoletime>>, Status> {
-    match get_roletime(ckey, &database.pool).await {
+    match get_roletime(&ckey, &database.pool).await {
         Ok(roletimes) => Ok(Json::Ok(roletimes)),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(_) => Err(Status::InternalServerError),
     }
 }
 
+#[get("/player/roletime/history?<ckey>&<job>&<bucket>&<from>&<to>")]
+pub async fn roletime_history(
+    ckey: Ckey,
+    job: Option<&str>,
+    bucket: Strict<HistoryBucket>,
+    from: Strict<Date>,
+    to: Strict<Date>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<RoletimeHistory>>, Status> {
+    match get_roletime_history(
+        &ckey,
+        job,
+        bucket.0.unwrap_or_default(),
+        from.0.map(|date| date.0),
+        to.0.map(|date| date.0),
+        &database.pool,
+    )
+    .await
+    {
+        Ok(history) => Ok(Json::Ok(history)),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
 #[get("/player/roletime/top?<job>")]
 pub async fn top(
     job: &str,
@@ -76,33 +147,149 @@ pub async fn top(
     Ok(Json::Ok(roletimes))
 }
 
-#[get("/player/activity?<ckey>")]
+#[allow(clippy::too_many_arguments)]
+#[get("/player/roletime/leaderboard?<job>&<department>&<period>&<page>&<per_page>&<ckey>")]
+pub async fn leaderboard(
+    job: Option<&str>,
+    department: Option<&str>,
+    period: Strict<LeaderboardPeriod>,
+    page: Strict<u32>,
+    per_page: Strict<u32>,
+    ckey: Strict<Ckey>,
+    database: &State<Database>,
+    config: &State<Config>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let page = page.0.unwrap_or(1);
+    let per_page = per_page.0.unwrap_or(25);
+
+    if page == 0 || !(1..=100).contains(&per_page) {
+        return Err(Status::BadRequest);
+    }
+
+    // with neither a job nor a department this ranks overall living time
+    let jobs = match (job, department) {
+        (Some(_), Some(_)) => return Err(Status::BadRequest),
+        (Some(job), None) => vec![job.to_string()],
+        (None, Some(department)) => match config
+            .departments
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(department))
+        {
+            Some((_, jobs)) if !jobs.is_empty() => jobs.clone(),
+            _ => return Err(Status::NotFound),
+        },
+        (None, None) => vec!["Living".to_string()],
+    };
+
+    let offset = (page as u64 - 1) * per_page as u64;
+
+    match get_leaderboard(
+        &jobs,
+        period.0.unwrap_or_default(),
+        offset,
+        per_page,
+        ckey.0.as_ref(),
+        &database.pool,
+    )
+    .await
+    {
+        Ok(leaderboard) => Ok(Json::Ok(json!({
+            "entries": leaderboard.entries,
+            "total": leaderboard.total,
+            "page": page,
+            "per_page": per_page,
+            "player": leaderboard.player,
+        }))),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/player/activity?<ckey>&<from>&<to>")]
 pub async fn activity(
-    ckey: &str,
+    ckey: Ckey,
+    from: Strict<Date>,
+    to: Strict<Date>,
     database: &State<Database>,
     _api_key: ApiKey,
 ) -> Result<Json<Vec<(String, i64)>>, Status> {
-    match get_activity(ckey, &database.pool).await {
+    let (from, to) = date_range(from, to)?;
+
+    match get_activity(&ckey, from, to, &database.pool).await {
         Ok(activity) => Ok(Json::Ok(activity)),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(_) => Err(Status::InternalServerError),
     }
 }
 
+// leaving out the ckey gives the community-wide heatmap
+#[get("/player/activity/heatmap?<ckey>&<from>&<to>&<timezone>")]
+pub async fn heatmap(
+    ckey: Strict<Ckey>,
+    from: Strict<Date>,
+    to: Strict<Date>,
+    timezone: Option<&str>,
+    database: &State<Database>,
+    config: &State<Config>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let (from, to) = date_range(from, to)?;
+
+    let timezone = match timezone {
+        Some(timezone) => timezone.parse::<Tz>().map_err(|_| Status::BadRequest)?,
+        None => Tz::UTC,
+    };
+
+    let heatmap = get_activity_heatmap(
+        ckey.0.as_ref(),
+        from,
+        to,
+        config.database.timezone,
+        timezone,
+        &database.pool,
+    )
+    .await;
+
+    match heatmap {
+        Ok(heatmap) => Ok(Json::Ok(json!({
+            "timezone": timezone.name(),
+            "weekdays": ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"],
+            "heatmap": heatmap,
+        }))),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+fn date_range(
+    from: Strict<Date>,
+    to: Strict<Date>,
+) -> Result<(Option<NaiveDate>, Option<NaiveDate>), Status> {
+    let (from, to) = (from.0.map(|date| date.0), to.0.map(|date| date.0));
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(Status::BadRequest);
+        }
+    }
+
+    Ok((from, to))
+}
+
 #[get("/player/discord?<ckey>&<discord_id>")]
 pub async fn discord(
-    ckey: Option<&str>,
+    ckey: Strict<Ckey>,
     discord_id: Option<&str>,
     database: &State<Database>,
     config: &State<Config>,
     _api_key: ApiKey,
 ) -> Result<Json<Value>, Status> {
-    if ckey.is_some() ^ discord_id.is_none() {
+    if ckey.0.is_some() ^ discord_id.is_none() {
         return Err(Status::BadRequest);
     }
 
-    if let Some(ckey) = ckey {
-        return match fetch_discord_by_ckey(ckey, &config.discord.token, &database.pool).await {
+    if let Some(ckey) = ckey.0 {
+        return match fetch_discord_by_ckey(&ckey, &config.discord.token, &database.pool).await {
             Ok(user) => Ok(Json::Ok(json!(user))),
             Err(Error::PlayerNotFound) => Err(Status::NotFound),
             Err(Error::NotLinked) => Err(Status::Conflict),
@@ -110,7 +297,7 @@ pub async fn discord(
         };
     } else if let Some(discord_id) = discord_id {
         return match get_ckey_by_discord_id(discord_id, &database.pool).await {
-            Ok(ckey) => Ok(Json::Ok(Value::String(ckey))),
+            Ok(ckey) => Ok(Json::Ok(Value::String(ckey.to_string()))),
             Err(Error::NotLinked) => Err(Status::Conflict),
             Err(_) => Err(Status::InternalServerError),
         };
@@ -119,15 +306,292 @@ pub async fn discord(
     unreachable!()
 }
 
+const BULK_DISCORD_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct BulkDiscordData {
+    #[serde(default)]
+    ckeys: Vec<Ckey>,
+    #[serde(default)]
+    discord_ids: Vec<String>,
+}
+
+#[post("/player/discord/bulk", data = "<data>")]
+pub async fn discord_bulk(
+    data: json::Json<BulkDiscordData>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<BulkDiscordLinks>, Status> {
+    if data.ckeys.len() > BULK_DISCORD_LIMIT || data.discord_ids.len() > BULK_DISCORD_LIMIT {
+        return Err(Status::PayloadTooLarge);
+    }
+
+    // ids are matched numerically so "0123" and "123" resolve to the same link
+    let Ok(discord_ids) = data
+        .discord_ids
+        .iter()
+        .map(|id| id.parse())
+        .collect::<Result<Vec<u64>, _>>()
+    else {
+        return Err(Status::BadRequest);
+    };
+
+    match get_bulk_discord_links(&data.ckeys, &discord_ids, &database.pool).await {
+        Ok(links) => Ok(Json::Ok(links)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
 #[get("/player/achievements?<ckey>")]
 pub async fn achievements(
-    ckey: &str,
+    ckey: Ckey,
     database: &State<Database>,
     _api_key: ApiKey,
 ) -> Result<Json<Value>, Status> {
-    match get_achievements(ckey, &database.pool).await {
+    match get_achievements(&ckey, &database.pool).await {
         Ok(achievements) => Ok(Json::Ok(json!(achievements))),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(_) => Err(Status::InternalServerError),
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+#[get("/player/notes?<ckey>&<type>&<severity>&<secret>&<expired>&<deleted>")]
+pub async fn notes(
+    ckey: Ckey,
+    r#type: Option<MessageType>,
+    severity: Option<Severity>,
+    secret: Option<bool>,
+    expired: Option<bool>,
+    deleted: Option<bool>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Vec<Message>>, Status> {
+    // secret notes are admin-only, so scoped keys can't ask for them
+    if secret == Some(true) && !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    let filter = MessageFilter {
+        message_type: r#type,
+        severity,
+        secret,
+        include_secret: api_key.privileged,
+        include_expired: expired.unwrap_or(false),
+        include_deleted: deleted.unwrap_or(false),
+    };
+
+    match get_messages(&ckey, &filter, &database.pool).await {
+        Ok(messages) => Ok(Json::Ok(messages)),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/player/related?<ckey>&<days>&<depth>")]
+pub async fn related(
+    ckey: Ckey,
+    days: Option<u32>,
+    depth: Option<u8>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    // shared ips and computer ids are for the main key only
+    if !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    let days = days.unwrap_or(180);
+    let depth = depth.unwrap_or(1);
+
+    if !(1..=3650).contains(&days) || !(1..=2).contains(&depth) {
+        return Err(Status::BadRequest);
+    }
+
+    match get_related(&ckey, days, depth, &database.pool).await {
+        Ok(related) => Ok(Json::Ok(json!({
+            "ckey": ckey,
+            "days": days,
+            "related": related,
+        }))),
+        Err(Error::PlayerNotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+const PROFILE_SECTIONS: [&str; 8] = [
+    "player",
+    "roletime",
+    "characters",
+    "activity",
+    "achievements",
+    "bans",
+    "patreon",
+    "totals",
+];
+
+// role_time rows that track experience types rather than actual jobs
+const EXP_TYPES: [&str; 14] = [
+    "Living",
+    "Ghost",
+    "Crew",
+    "Command",
+    "Engineering",
+    "Medical",
+    "Science",
+    "Supply",
+    "Security",
+    "Silicon",
+    "Service",
+    "Special",
+    "Antagonists",
+    "Admin",
+];
+
+async fn fetch_if<T>(wanted: bool, future: impl Future<Output = T>) -> Option<T> {
+    match wanted {
+        true => Some(future.await),
+        false => None,
+    }
+}
+
+#[get("/player/profile?<ckey>&<include>")]
+pub async fn profile(
+    ckey: Ckey,
+    include: Option<&str>,
+    database: &State<Database>,
+    config: &State<Config>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let sections = match include {
+        Some(include) => include
+            .split(',')
+            .map(str::trim)
+            .filter(|section| !section.is_empty())
+            .collect::<HashSet<_>>(),
+        None => HashSet::from(PROFILE_SECTIONS),
+    };
+
+    if sections
+        .iter()
+        .any(|section| !PROFILE_SECTIONS.contains(section))
+    {
+        return Err(Status::BadRequest);
+    }
+
+    let pool = &database.pool;
+
+    let player = match get_player(&ckey, pool).await {
+        Ok(player) => player,
+        Err(Error::PlayerNotFound) => return Err(Status::NotFound),
+        Err(_) => return Err(Status::InternalServerError),
+    };
+
+    let wants = |section: &str| sections.contains(section);
+    let totals = wants("totals");
+
+    let (roletime, characters, activity, achievements, bans, patron, discord_id, rounds_played) = tokio::join!(
+        fetch_if(wants("roletime") || totals, get_roletime(&ckey, pool)),
+        fetch_if(
+            wants("characters"),
+            get_characters(&ckey, &config.excluded_roles, pool)
+        ),
+        fetch_if(wants("activity"), get_activity(&ckey, None, None, pool)),
+        fetch_if(wants("achievements"), get_achievements(&ckey, pool)),
+        fetch_if(wants("bans"), get_ban(&ckey, false, None, pool)),
+        fetch_if(
+            wants("patreon") || totals,
+            get_patron_tier(&ckey, pool, &config.discord)
+        ),
+        fetch_if(totals, get_discord_id_by_ckey(&ckey, pool)),
+        fetch_if(totals, get_rounds_played(&ckey, pool)),
+    );
+
+    let mut profile = json!({});
+
+    if wants("player") {
+        profile["player"] = json!(player);
+    }
+
+    let roletime = roletime
+        .transpose()
+        .map_err(|_| Status::InternalServerError)?;
+
+    let patron = patron
+        .transpose()
+        .map_err(|_| Status::InternalServerError)?;
+
+    if let Some(characters) = characters {
+        let characters = characters.map_err(|_| Status::InternalServerError)?;
+        profile["characters"] = json!(characters);
+    }
+
+    if let Some(activity) = activity {
+        let activity = activity.map_err(|_| Status::InternalServerError)?;
+        profile["activity"] = json!(activity);
+    }
+
+    if let Some(achievements) = achievements {
+        let achievements = achievements.map_err(|_| Status::InternalServerError)?;
+        profile["achievements"] = json!(achievements);
+    }
+
+    if let Some(bans) = bans {
+        let bans = bans.map_err(|_| Status::InternalServerError)?;
+        profile["bans"] = json!(bans);
+    }
+
+    if let (true, Some((patron, tier))) = (wants("patreon"), &patron) {
+        profile["patreon"] = json!({
+            "patron": patron,
+            "tier": tier.map(tier_to_json),
+        });
+    }
+
+    if totals {
+        let roletime = roletime.as_deref().unwrap_or_default();
+
+        let minutes_of = |job: &str| {
+            roletime
+                .iter()
+                .find(|roletime| roletime.job == job)
+                .map_or(0, |roletime| roletime.minutes)
+        };
+
+        let living_minutes = minutes_of("Living");
+        let ghost_minutes = minutes_of("Ghost");
+
+        let favourite_job = roletime
+            .iter()
+            .filter(|roletime| !EXP_TYPES.contains(&roletime.job.as_str()))
+            .max_by_key(|roletime| roletime.minutes)
+            .map(|roletime| roletime.job.clone());
+
+        let linked = match discord_id {
+            Some(Ok(_)) => true,
+            Some(Err(Error::NotLinked)) | None => false,
+            Some(Err(_)) => return Err(Status::InternalServerError),
+        };
+
+        let rounds_played = rounds_played
+            .transpose()
+            .map_err(|_| Status::InternalServerError)?
+            .unwrap_or(0);
+
+        profile["totals"] = json!({
+            "playtime_minutes": living_minutes + ghost_minutes,
+            "living_minutes": living_minutes,
+            "ghost_minutes": ghost_minutes,
+            "favourite_job": favourite_job,
+            "rounds_played": rounds_played,
+            "linked": linked,
+            "patron": patron.is_some_and(|(patron, _)| patron),
+        });
+    }
+
+    if wants("roletime") {
+        profile["roletime"] = json!(roletime);
+    }
+
+    Ok(Json::Ok(profile))
+}
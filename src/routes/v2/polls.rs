@@ -0,0 +1,52 @@
+use rocket::{get, http::Status, State};
+use serde_json::Value;
+
+use crate::{database::*, Database};
+
+use super::{common::ApiKey, Date, Json, Page, Strict};
+
+#[allow(clippy::too_many_arguments)]
+#[get("/polls?<status>&<type>&<from>&<to>&<hidden>&<page..>")]
+pub async fn index(
+    status: Strict<PollStatus>,
+    r#type: Strict<PollType>,
+    from: Strict<Date>,
+    to: Strict<Date>,
+    hidden: Strict<bool>,
+    page: Page<u32>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let include_hidden = hidden.0.unwrap_or(false);
+
+    if include_hidden && !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    let filter = PollFilter {
+        status: status.0,
+        polltype: r#type.0,
+        from: from.0.map(|date| date.0),
+        to: to.0.map(|date| date.0),
+        include_hidden,
+    };
+
+    match get_polls(&filter, page.after.0, page.limit, &database.pool).await {
+        Ok(polls) => Ok(page.respond(polls, |poll| poll.id)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/polls/<id>")]
+pub async fn poll(
+    id: u32,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<PollDetails>, Status> {
+    // hidden polls and who replied to text polls are only shown to the main key
+    match get_poll(id, api_key.privileged, api_key.privileged, &database.pool).await {
+        Ok(Some(details)) => Ok(Json::Ok(details)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
@@ -0,0 +1,107 @@
+use chrono::{Duration, Utc};
+use rocket::{get, http::Status, State};
+use serde_json::{json, Value};
+
+use crate::{database::*, Config, Database};
+
+use super::{common::ApiKey, Date, Json, Page, Strict};
+
+#[get("/round/<id>")]
+pub async fn index(
+    id: u32,
+    config: &State<Config>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<RoundDetails>, Status> {
+    match get_round(id, config, &database.pool).await {
+        Ok(Some(round)) => Ok(Json::Ok(round)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/rounds?<from>&<to>&<map>&<server>&<min_duration>&<end_state>&<page..>")]
+pub async fn list(
+    from: Strict<Date>,
+    to: Strict<Date>,
+    map: Option<&str>,
+    server: Option<&str>,
+    min_duration: Strict<u32>,
+    end_state: Option<&str>,
+    page: Page<u32>,
+    config: &State<Config>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let server_port = match server {
+        Some(server) => Some(config.server_port(server).ok_or(Status::BadRequest)?),
+        None => None,
+    };
+
+    let filter = RoundFilter {
+        from: from.0.map(|date| date.0),
+        to: to.0.map(|date| date.0),
+        map,
+        server_port,
+        min_duration: min_duration.0,
+        end_state,
+    };
+
+    match get_rounds(&filter, page.after.0, page.limit, config, &database.pool).await {
+        Ok(rounds) => Ok(page.respond(rounds, |round| round.id)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// the default window for stats, and the longest range they're computed over since every round
+// in it is read in full
+const STATS_DAYS: i64 = 90;
+const STATS_MAX_DAYS: i64 = 366;
+
+#[allow(clippy::too_many_arguments)]
+#[get("/rounds/stats?<from>&<to>&<map>&<server>&<min_duration>&<end_state>")]
+pub async fn stats(
+    from: Strict<Date>,
+    to: Strict<Date>,
+    map: Option<&str>,
+    server: Option<&str>,
+    min_duration: Strict<u32>,
+    end_state: Option<&str>,
+    config: &State<Config>,
+    database: &State<Database>,
+    _api_key: ApiKey,
+) -> Result<Json<Value>, Status> {
+    let server_port = match server {
+        Some(server) => Some(config.server_port(server).ok_or(Status::BadRequest)?),
+        None => None,
+    };
+
+    let to = to.0.map_or_else(|| Utc::now().date_naive(), |date| date.0);
+    let from = from
+        .0
+        .map_or_else(|| to - Duration::days(STATS_DAYS), |date| date.0);
+
+    if from > to || (to - from).num_days() > STATS_MAX_DAYS {
+        return Err(Status::BadRequest);
+    }
+
+    let filter = RoundFilter {
+        from: Some(from),
+        to: Some(to),
+        map,
+        server_port,
+        min_duration: min_duration.0,
+        end_state,
+    };
+
+    match get_round_stats(&filter, config, &database.pool).await {
+        Ok(stats) => Ok(Json::Ok(json!({
+            "from": from.format("%Y-%m-%d").to_string(),
+            "to": to.format("%Y-%m-%d").to_string(),
+            "maps": stats.maps,
+            "months": stats.months,
+        }))),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
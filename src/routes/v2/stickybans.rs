@@ -0,0 +1,38 @@
+use rocket::{get, http::Status, State};
+
+use crate::{ckey::Ckey, database::*, Database};
+
+use super::{common::ApiKey, Json};
+
+#[get("/stickybans?<search>")]
+pub async fn index(
+    search: Option<&str>,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<Vec<Stickyban>>, Status> {
+    if !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    match get_stickybans(search, &database.pool).await {
+        Ok(stickybans) => Ok(Json::Ok(stickybans)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/stickybans/<ckey>")]
+pub async fn stickyban(
+    ckey: Ckey,
+    database: &State<Database>,
+    api_key: ApiKey,
+) -> Result<Json<StickybanDetails>, Status> {
+    if !api_key.privileged {
+        return Err(Status::Forbidden);
+    }
+
+    match get_stickyban(&ckey, &database.pool).await {
+        Ok(Some(stickyban)) => Ok(Json::Ok(stickyban)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
@@ -2,6 +2,7 @@ use rocket::{http::Status, post, serde::json, State};
 use serde::Deserialize;
 
 use crate::{
+    ckey::Ckey,
     database::{error::Error, *},
     Database,
 };
@@ -12,7 +13,7 @@ use super::{common::ApiKey, Json};
 pub struct VerifyData<'r> {
     discord_id: &'r str,
     one_time_token: Option<&'r str>,
-    ckey: Option<&'r str>,
+    ckey: Option<Ckey>,
     skip_ckey: Option<bool>,
 }
 
@@ -29,13 +30,13 @@ pub async fn index(
     match verify_discord(
         data.discord_id,
         data.one_time_token,
-        data.ckey,
+        data.ckey.as_ref(),
         data.skip_ckey,
         &database.pool,
     )
     .await
     {
-        Ok(ckey) => Ok(Json::Ok(ckey)),
+        Ok(ckey) => Ok(Json::Ok(ckey.map(|ckey| ckey.to_string()))),
         Err(Error::DiscordInUse(ckey)) => Ok(Json::Conflict(Some(ckey))),
         Err(Error::CkeyInUse(discord_id)) => Ok(Json::Conflict(Some(format!("@{discord_id}")))),
         Err(Error::TokenInvalid) => Err(Status::NotFound),
@@ -47,7 +48,7 @@ pub async fn index(
 #[derive(Deserialize)]
 pub struct UnverifyData<'r> {
     discord_id: Option<&'r str>,
-    ckey: Option<&'r str>,
+    ckey: Option<Ckey>,
 }
 
 #[post("/unverify", data = "<data>")]
@@ -60,7 +61,7 @@ pub async fn unverify(
         return Err(Status::BadRequest);
     }
 
-    match unverify_discord(data.discord_id, data.ckey, &database.pool).await {
+    match unverify_discord(data.discord_id, data.ckey.as_ref(), &database.pool).await {
         Ok(account) => Ok(Json::Ok(account)),
         Err(Error::PlayerNotFound) => Err(Status::NotFound),
         Err(Error::NotLinked) => Err(Status::Conflict),